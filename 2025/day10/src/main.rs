@@ -1,23 +1,50 @@
 use std::{
-    cmp::Reverse,
-    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, BinaryHeap, HashMap, btree_map::Entry},
+    env,
+    error::Error,
+    fmt,
     fs::File,
     io::{self, BufRead, BufReader},
 };
 
-use good_lp::{Expression, Solution, SolverModel, default_solver, variable, variables};
+use num_rational::Rational64;
 
 fn main() -> io::Result<()> {
     let input = read_input()?;
 
-    let p1_result = p1(&input);
+    let p1_result = p1(&input).map_err(invalid_data)?;
 
     println!("Part 1 result: {p1_result}");
 
-    let p2_result = p2(&input);
+    let p2_result = p2(&input).map_err(invalid_data)?;
 
     println!("Part 2 result: {p2_result}");
 
+    if env::args().any(|arg| arg == "--plans") {
+        for (i, machine) in parse_machines(&input)
+            .map_err(invalid_data)?
+            .iter()
+            .enumerate()
+        {
+            let lights = machine.configure_lights();
+            let joltages = machine.configure_joltages().map_err(invalid_data)?;
+            println!("Machine {}", i + 1);
+            print_plan(
+                machine,
+                "lights",
+                lights.as_ref().map(|s| &s.presses),
+                |p| machine.verify_lights(p),
+            );
+            if let Some(lights) = &lights {
+                println!("  lights: {} optimal plans", lights.optimal_solutions);
+            }
+            print_plan(machine, "joltages", joltages.as_ref(), |p| {
+                machine.verify_joltages(p)
+            });
+        }
+    }
+
     Ok(())
 }
 
@@ -26,114 +53,386 @@ fn read_input() -> io::Result<Vec<String>> {
     BufReader::new(file).lines().collect::<Result<_, _>>()
 }
 
-fn p1(input: &[String]) -> u32 {
+fn print_plan(
+    machine: &Machine,
+    name: &str,
+    presses: Option<&Presses>,
+    verify: impl Fn(&Presses) -> bool,
+) {
+    match presses {
+        Some(presses) => {
+            let status = if verify(presses) {
+                "verified"
+            } else {
+                "MISMATCH"
+            };
+            let cost = machine
+                .cost(presses)
+                .map_or_else(|| "overflow".to_string(), |cost| cost.to_string());
+            println!(
+                "  {name}: {} presses, cost {cost}, {presses} ({status})",
+                presses.total()
+            );
+        }
+        None => println!("  {name}: unreachable"),
+    }
+}
+
+fn invalid_data(error: MachineError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn parse_machines(input: &[String]) -> Result<Vec<Machine>, MachineError> {
     input
         .iter()
-        .map(|line| Machine::from(line).configure_lights())
-        .sum::<u32>()
+        .enumerate()
+        .map(|(i, line)| Machine::parse(i + 1, line))
+        .collect()
 }
 
-fn p2(input: &[String]) -> u32 {
-    input
+fn p1(input: &[String]) -> Result<u64, MachineError> {
+    parse_machines(input)?
         .iter()
-        .map(|line| Machine::from(line).configure_joltages())
-        .sum::<u32>()
+        .zip(1..)
+        .try_fold(0_u64, |total, (machine, line)| {
+            let solution = machine
+                .configure_lights()
+                .ok_or(MachineError::UnreachableLights { line })?;
+            machine
+                .cost(&solution.presses)
+                .and_then(|cost| total.checked_add(cost))
+                .ok_or(MachineError::CostOverflow { line })
+        })
 }
 
-fn slice_to_bitmask(slice: &[bool]) -> u16 {
-    slice
+fn p2(input: &[String]) -> Result<u64, MachineError> {
+    parse_machines(input)?
         .iter()
-        .enumerate()
-        .fold(0, |acc, (i, &on)| if on { acc | (1 << i) } else { acc })
+        .zip(1..)
+        .try_fold(0_u64, |total, (machine, line)| {
+            let presses = machine
+                .configure_joltages()?
+                .ok_or(MachineError::UnreachableJoltages { line })?;
+            machine
+                .cost(&presses)
+                .and_then(|cost| total.checked_add(cost))
+                .ok_or(MachineError::CostOverflow { line })
+        })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum MachineError {
+    InvalidLights {
+        line: usize,
+        value: String,
+    },
+    InvalidButton {
+        line: usize,
+        value: String,
+    },
+    InvalidJoltages {
+        line: usize,
+        value: String,
+    },
+    ButtonOutOfRange {
+        line: usize,
+        index: usize,
+        lights: usize,
+    },
+    CounterMismatch {
+        line: usize,
+        lights: usize,
+        counters: usize,
+    },
+    UnreachableLights {
+        line: usize,
+    },
+    UnreachableJoltages {
+        line: usize,
+    },
+    CostOverflow {
+        line: usize,
+    },
 }
 
-fn indices_to_bitmask(indices: &[usize]) -> u16 {
-    indices.iter().fold(0, |acc, &i| acc | (1 << i))
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MachineError::InvalidLights { line, value } => {
+                write!(f, "invalid indicator lights {value:?} at line {line}")
+            }
+            MachineError::InvalidButton { line, value } => {
+                write!(f, "invalid button {value:?} at line {line}")
+            }
+            MachineError::InvalidJoltages { line, value } => {
+                write!(f, "invalid joltage requirements {value:?} at line {line}")
+            }
+            MachineError::ButtonOutOfRange {
+                line,
+                index,
+                lights,
+            } => write!(
+                f,
+                "button wired to light {index} at line {line}, the machine has {lights} lights"
+            ),
+            MachineError::CounterMismatch {
+                line,
+                lights,
+                counters,
+            } => write!(
+                f,
+                "line {line} has {counters} joltage counters for {lights} lights"
+            ),
+            MachineError::UnreachableLights { line } => {
+                write!(f, "indicator lights at line {line} can't be reached")
+            }
+            MachineError::UnreachableJoltages { line } => {
+                write!(f, "joltage requirements at line {line} can't be reached")
+            }
+            MachineError::CostOverflow { line } => {
+                write!(f, "total cost overflows at line {line}")
+            }
+        }
+    }
+}
+
+impl Error for MachineError {}
+
+/**
+ * Bitset of any length, for lights or buttons
+ */
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    fn empty(len: usize) -> Self {
+        BitSet {
+            words: vec![0; len.div_ceil(64)],
+        }
+    }
+
+    fn get(&self, i: usize) -> bool {
+        self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn flip(&mut self, i: usize) {
+        self.words[i / 64] ^= 1 << (i % 64);
+    }
+
+    fn toggle(&mut self, other: &BitSet) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word ^= other;
+        }
+    }
 }
 
 struct Machine {
+    line: usize,
     indicator_lights: Vec<bool>,
     buttons: Vec<Vec<usize>>,
-    joltage_requirements: [Joltage; MAX_JOLTAGES],
+    /** Cost of one press of each button, 1 unless specified */
+    costs: Vec<u32>,
+    /** Maximum number of presses of each button, if any */
+    caps: Vec<Option<u32>>,
+    joltage_requirements: Vec<Joltage>,
 }
 
 type Joltage = u16;
-const MAX_JOLTAGES: usize = 10;
 
 impl Machine {
     /**
-     * BFS to find the minimum number of button presses to match the indicator lights
+     * Gaussian elimination over GF(2), each light being the XOR of the buttons wired to it
+     * Every solution is a particular one plus any choice of free buttons, which are added one
+     * at a time keeping the cheapest choice per set of pivot presses, so the work is bounded
+     * by 2 to the power of the pivots as well as of the free buttons
+     * Buttons capped at 0 presses are left out, any other cap allows the single useful press
      */
-    fn configure_lights(&self) -> u32 {
-        let mut queue = VecDeque::new();
-        let mut visited = HashSet::new();
-
-        let starting_lights = 0u16; // Bitmask
-        let indicator_lights = slice_to_bitmask(&self.indicator_lights);
-        let buttons = self
-            .buttons
+    fn configure_lights(&self) -> Option<LightsSolution> {
+        let buttons = self.buttons.len();
+        let usable = |button: usize| self.caps[button] != Some(0);
+        let mut rows: Vec<BitSet> = self
+            .indicator_lights
             .iter()
-            .map(|indices| indices_to_bitmask(indices))
-            .collect::<Vec<u16>>();
+            .enumerate()
+            .map(|(light, &on)| {
+                let mut row = BitSet::empty(buttons + 1);
+                for (button, wired) in self.buttons.iter().enumerate() {
+                    if usable(button) && wired.contains(&light) {
+                        row.flip(button);
+                    }
+                }
+                if on {
+                    row.flip(buttons);
+                }
+                row
+            })
+            .collect();
 
-        visited.insert(starting_lights);
-        queue.push_back((starting_lights, 0));
+        let mut pivots = Vec::new();
+        for column in 0..buttons {
+            let rank = pivots.len();
+            let Some(pivot_row) = (rank..rows.len()).find(|&row| rows[row].get(column)) else {
+                continue;
+            };
+            rows.swap(rank, pivot_row);
 
-        while let Some((lights, buttons_pressed)) = queue.pop_front() {
-            if lights == indicator_lights {
-                return buttons_pressed;
+            let pivot_row = rows[rank].clone();
+            for (row, values) in rows.iter_mut().enumerate() {
+                if row != rank && values.get(column) {
+                    values.toggle(&pivot_row);
+                }
             }
+            pivots.push(column);
+        }
 
-            for button in &buttons {
-                let new_lights = lights ^ *button;
-                if visited.insert(new_lights) {
-                    queue.push_back((new_lights, buttons_pressed + 1));
+        if rows[pivots.len()..].iter().any(|row| row.get(buttons)) {
+            return None;
+        }
+
+        // Cheapest presses of the free buttons so far, keyed by the pivot buttons they imply
+        let mut particular = BitSet::empty(pivots.len());
+        for (rank, row) in rows.iter().enumerate().take(pivots.len()) {
+            if row.get(buttons) {
+                particular.flip(rank);
+            }
+        }
+        let mut partials = BTreeMap::from([(
+            particular,
+            PartialLights {
+                cost: 0,
+                optimal_solutions: 1,
+                presses: BitSet::empty(buttons),
+            },
+        )]);
+        for free in (0..buttons).filter(|&button| usable(button) && !pivots.contains(&button)) {
+            let mut implied = BitSet::empty(pivots.len());
+            for (rank, row) in rows.iter().enumerate().take(pivots.len()) {
+                if row.get(free) {
+                    implied.flip(rank);
                 }
             }
+            let mut next = BTreeMap::new();
+            for (pivot_presses, partial) in partials {
+                let mut pressed_pivots = pivot_presses.clone();
+                pressed_pivots.toggle(&implied);
+                let mut pressed = PartialLights {
+                    cost: partial.cost + u64::from(self.costs[free]),
+                    optimal_solutions: partial.optimal_solutions,
+                    presses: partial.presses.clone(),
+                };
+                pressed.presses.flip(free);
+                PartialLights::merge(&mut next, pivot_presses, partial);
+                PartialLights::merge(&mut next, pressed_pivots, pressed);
+            }
+            partials = next;
         }
 
-        u32::MAX
+        let mut best: Option<PartialLights> = None;
+        for (pivot_presses, mut partial) in partials {
+            for (rank, &pivot) in pivots.iter().enumerate() {
+                if pivot_presses.get(rank) {
+                    partial.cost += u64::from(self.costs[pivot]);
+                    partial.presses.flip(pivot);
+                }
+            }
+            match &mut best {
+                Some(best) if partial.cost == best.cost => {
+                    best.optimal_solutions = best
+                        .optimal_solutions
+                        .saturating_add(partial.optimal_solutions);
+                }
+                Some(best) if partial.cost > best.cost => {}
+                _ => best = Some(partial),
+            }
+        }
+        let best = best?;
+
+        Some(LightsSolution {
+            presses: Presses {
+                per_button: (0..buttons)
+                    .map(|b| u32::from(best.presses.get(b)))
+                    .collect(),
+            },
+            optimal_solutions: best.optimal_solutions,
+        })
     }
 
     /**
-     * Boring linear programming to find the minimum number of button presses to meet the joltage requirements
+     * Gaussian elimination over rationals on the counters, then a bounded search over free buttons
+     * None when the counters can't be reached, an error when the scaled costs overflow
      */
-    fn configure_joltages(&self) -> u32 {
-        let mut vars = variables!();
-        let button_vars = (0..self.buttons.len())
-            .map(|_| vars.add(variable().integer().min(0)))
-            .collect::<Vec<_>>();
-
-        let mut problem = vars
-            .minimise(button_vars.iter().sum::<Expression>())
-            .using(default_solver);
-
-        // Don't talk to me, you already ruined the fun of solving this with A*
-        problem.set_parameter("log", "0");
-
-        for (joltage_idx, &required_joltage) in self.joltage_requirements.iter().enumerate() {
-            let mut expr = Expression::from(0);
-            for (button_idx, button) in self.buttons.iter().enumerate() {
-                if button.contains(&joltage_idx) {
-                    expr += button_vars[button_idx];
-                }
-            }
-            problem = problem.with(expr.eq(required_joltage));
+    fn configure_joltages(&self) -> Result<Option<Presses>, MachineError> {
+        match ReducedSystem::from(self)? {
+            Some(system) => system.minimum_presses(),
+            None => Ok(None),
         }
+    }
 
-        let solution = problem.solve().unwrap();
-
-        // Cast is safe as constraints above ensure non-negative integers
-        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-        button_vars
+    /**
+     * None when the total cost overflows u64
+     */
+    fn cost(&self, presses: &Presses) -> Option<u64> {
+        presses
+            .per_button
             .iter()
-            .map(|var| solution.value(*var) as u32)
-            .sum()
+            .zip(&self.costs)
+            .try_fold(0_u64, |total, (&count, &cost)| {
+                total.checked_add(u64::from(count).checked_mul(u64::from(cost))?)
+            })
+    }
+
+    fn within_caps(&self, presses: &Presses) -> bool {
+        presses.per_button.len() == self.buttons.len()
+            && presses
+                .per_button
+                .iter()
+                .zip(&self.caps)
+                .all(|(&count, cap)| cap.is_none_or(|cap| count <= cap))
+    }
+
+    /**
+     * Replays the presses from all lights off
+     */
+    fn verify_lights(&self, presses: &Presses) -> bool {
+        let mut lights = vec![false; self.indicator_lights.len()];
+        self.within_caps(presses)
+            && presses.sequence().all(|button| {
+                self.buttons[button]
+                    .iter()
+                    .all(|&i| lights.get_mut(i).map(|light| *light = !*light).is_some())
+            })
+            && lights == self.indicator_lights
+    }
+
+    /**
+     * Replays the presses from all counters at 0
+     */
+    fn verify_joltages(&self, presses: &Presses) -> bool {
+        let mut joltages = vec![0u32; self.joltage_requirements.len()];
+        self.within_caps(presses)
+            && presses
+                .per_button
+                .iter()
+                .zip(&self.buttons)
+                .all(|(&count, button)| {
+                    button.iter().all(|&i| {
+                        joltages
+                            .get_mut(i)
+                            .map(|joltage| *joltage += count)
+                            .is_some()
+                    })
+                })
+            && joltages
+                .iter()
+                .zip(&self.joltage_requirements)
+                .all(|(&joltage, &required)| joltage == u32::from(required))
     }
 
     /**
      * A* to find the minimum number of button presses to meet the joltage requirements
-     * Unused because linear programming is mandatory
+     * Unused because elimination is much faster
      * But I kept it because I like it and I need to train A*
      */
     #[allow(dead_code)]
@@ -141,9 +440,9 @@ impl Machine {
         let mut open_set = BinaryHeap::new(); // Min-heap using Reverse on BinaryHeap
         let mut g_score = HashMap::new();
 
-        let starting_joltages = [0u16; MAX_JOLTAGES];
+        let starting_joltages = vec![0; self.joltage_requirements.len()];
 
-        g_score.insert(starting_joltages, 0);
+        g_score.insert(starting_joltages.clone(), 0);
         open_set.push(Reverse((0, 0, starting_joltages)));
 
         while let Some(Reverse((_priority, buttons_pressed, joltages))) = open_set.pop() {
@@ -152,7 +451,7 @@ impl Machine {
             }
 
             for button in &self.buttons {
-                let new_joltages = self.press_joltages_button(joltages, button);
+                let new_joltages = self.press_joltages_button(&joltages, button);
 
                 if let Some(new_joltages) = new_joltages {
                     let new_buttons_pressed = buttons_pressed + 1;
@@ -160,7 +459,7 @@ impl Machine {
                         .get(&new_joltages)
                         .is_none_or(|&score| new_buttons_pressed < score)
                     {
-                        g_score.insert(new_joltages, new_buttons_pressed);
+                        g_score.insert(new_joltages.clone(), new_buttons_pressed);
                         let priority = new_buttons_pressed + self.joltage_heuristic(&new_joltages);
                         open_set.push(Reverse((priority, buttons_pressed + 1, new_joltages)));
                     }
@@ -173,10 +472,10 @@ impl Machine {
 
     fn press_joltages_button(
         &self,
-        current_joltages: [Joltage; MAX_JOLTAGES],
+        current_joltages: &[Joltage],
         button: &[usize],
-    ) -> Option<[Joltage; MAX_JOLTAGES]> {
-        let mut new_joltages = current_joltages;
+    ) -> Option<Vec<Joltage>> {
+        let mut new_joltages = current_joltages.to_vec();
         for &idx in button {
             new_joltages[idx] += 1;
 
@@ -196,56 +495,469 @@ impl Machine {
     }
 }
 
-impl From<&String> for Machine {
-    fn from(s: &String) -> Self {
+/**
+ * Number of presses of each button
+ */
+#[derive(Debug, PartialEq, Eq)]
+struct Presses {
+    per_button: Vec<u32>,
+}
+
+impl Presses {
+    fn total(&self) -> u32 {
+        self.per_button.iter().sum()
+    }
+
+    /**
+     * Button indices in press order, the order itself doesn't matter
+     */
+    fn sequence(&self) -> impl Iterator<Item = usize> + '_ {
+        self.per_button
+            .iter()
+            .enumerate()
+            .flat_map(|(button, &count)| std::iter::repeat_n(button, count as usize))
+    }
+}
+
+impl fmt::Display for Presses {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pressed: Vec<String> = self
+            .per_button
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(button, count)| format!("button {button} x{count}"))
+            .collect();
+        write!(f, "[{}]", pressed.join(", "))
+    }
+}
+
+/**
+ * One of the cheapest ways to light the indicators, and how many there are
+ */
+struct LightsSolution {
+    presses: Presses,
+    /** Saturates at `u64::MAX` */
+    optimal_solutions: u64,
+}
+
+/**
+ * Cheapest presses of the free buttons decided so far, for a given set of pivot presses
+ */
+struct PartialLights {
+    cost: u64,
+    optimal_solutions: u64,
+    presses: BitSet,
+}
+
+impl PartialLights {
+    fn merge(partials: &mut BTreeMap<BitSet, PartialLights>, key: BitSet, partial: PartialLights) {
+        match partials.entry(key) {
+            Entry::Vacant(entry) => {
+                entry.insert(partial);
+            }
+            Entry::Occupied(mut entry) => {
+                let existing = entry.get_mut();
+                match partial.cost.cmp(&existing.cost) {
+                    Ordering::Less => *existing = partial,
+                    Ordering::Equal => {
+                        existing.optimal_solutions = existing
+                            .optimal_solutions
+                            .saturating_add(partial.optimal_solutions);
+                    }
+                    Ordering::Greater => {}
+                }
+            }
+        }
+    }
+}
+
+/**
+ * Reduced row echelon form of the counter equations, each pivot button being
+ * `pivot = (rhs - Σ coefficient * free button) / scale` with integers only
+ */
+struct ReducedSystem {
+    line: usize,
+    rows: Vec<ReducedRow>,
+    free_buttons: Vec<usize>,
+    /** A button can't be pressed more often than its cap or any counter it increases allows */
+    bounds: Vec<i64>,
+    costs: Vec<i64>,
+}
+
+struct ReducedRow {
+    pivot: usize,
+    scale: i64,
+    rhs: i64,
+    coefficients: Vec<i64>,
+}
+
+impl ReducedSystem {
+    /**
+     * None when the counters are inconsistent, whatever the presses
+     */
+    fn from(machine: &Machine) -> Result<Option<Self>, MachineError> {
+        let buttons = machine.buttons.len();
+        let mut matrix: Vec<Vec<Rational64>> = machine
+            .joltage_requirements
+            .iter()
+            .enumerate()
+            .map(|(counter, &required)| {
+                let mut row: Vec<Rational64> = machine
+                    .buttons
+                    .iter()
+                    .map(|button| Rational64::from_integer(i64::from(button.contains(&counter))))
+                    .collect();
+                row.push(Rational64::from_integer(i64::from(required)));
+                row
+            })
+            .collect();
+
+        let zero = Rational64::from_integer(0);
+        let mut pivots = Vec::new();
+        for column in 0..buttons {
+            let rank = pivots.len();
+            let Some(pivot_row) = (rank..matrix.len()).find(|&row| matrix[row][column] != zero)
+            else {
+                continue;
+            };
+            matrix.swap(rank, pivot_row);
+
+            let pivot = matrix[rank][column];
+            for value in &mut matrix[rank] {
+                *value /= pivot;
+            }
+            let pivot_row = matrix[rank].clone();
+            for (row, values) in matrix.iter_mut().enumerate() {
+                let factor = values[column];
+                if row != rank && factor != zero {
+                    for (value, &pivot_value) in values.iter_mut().zip(&pivot_row) {
+                        *value -= factor * pivot_value;
+                    }
+                }
+            }
+            pivots.push(column);
+        }
+
+        if matrix[pivots.len()..]
+            .iter()
+            .any(|row| row[buttons] != zero)
+        {
+            return Ok(None);
+        }
+
+        let overflow = MachineError::CostOverflow { line: machine.line };
+        let free_buttons: Vec<usize> = (0..buttons).filter(|b| !pivots.contains(b)).collect();
+        let rows = pivots
+            .iter()
+            .zip(&matrix)
+            .map(|(&pivot, row)| {
+                let scale = free_buttons
+                    .iter()
+                    .try_fold(*row[buttons].denom(), |scale, &free| {
+                        lcm(scale, *row[free].denom())
+                    })?;
+                // The scale is a multiple of every denominator
+                let integer =
+                    |value: Rational64| (scale / value.denom()).checked_mul(*value.numer());
+                Some(ReducedRow {
+                    pivot,
+                    scale,
+                    rhs: integer(row[buttons])?,
+                    coefficients: free_buttons
+                        .iter()
+                        .map(|&free| integer(row[free]))
+                        .collect::<Option<_>>()?,
+                })
+            })
+            .collect::<Option<_>>()
+            .ok_or(overflow)?;
+
+        let bounds = machine
+            .buttons
+            .iter()
+            .zip(&machine.caps)
+            .map(|(button, cap)| {
+                button
+                    .iter()
+                    .map(|&counter| i64::from(machine.joltage_requirements[counter]))
+                    .chain(cap.map(i64::from))
+                    .min()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        Ok(Some(ReducedSystem {
+            line: machine.line,
+            rows,
+            free_buttons,
+            bounds,
+            costs: machine.costs.iter().map(|&cost| i64::from(cost)).collect(),
+        }))
+    }
+
+    /**
+     * The scaled costs are checked in i64, the search adds up at most `u16::MAX` presses of
+     * each free button on top of them in i128
+     */
+    fn minimum_presses(&self) -> Result<Option<Presses>, MachineError> {
+        let overflow = || MachineError::CostOverflow { line: self.line };
+
+        // The total cost, scaled by every row scale, is linear in the free buttons
+        let scale = self
+            .rows
+            .iter()
+            .try_fold(1, |scale, row| lcm(scale, row.scale))
+            .ok_or_else(overflow)?;
+        let pivot_weight =
+            |row: &ReducedRow| (scale / row.scale).checked_mul(self.costs[row.pivot]);
+        let base_cost = self
+            .rows
+            .iter()
+            .try_fold(0_i64, |sum, row| {
+                sum.checked_add(pivot_weight(row)?.checked_mul(row.rhs)?)
+            })
+            .ok_or_else(overflow)?;
+        let weights = self
+            .free_buttons
+            .iter()
+            .enumerate()
+            .map(|(f, &button)| {
+                let pivots_cost = self.rows.iter().try_fold(0_i64, |sum, row| {
+                    sum.checked_add(pivot_weight(row)?.checked_mul(row.coefficients[f])?)
+                })?;
+                scale
+                    .checked_mul(self.costs[button])?
+                    .checked_sub(pivots_cost)
+                    .map(i128::from)
+            })
+            .collect::<Option<Vec<i128>>>()
+            .ok_or_else(overflow)?;
+
+        let mut search = FreeButtonSearch {
+            system: self,
+            weights,
+            values: Vec::with_capacity(self.free_buttons.len()),
+            best: None,
+        };
+        search.run(i128::from(base_cost));
+
+        Ok(search
+            .best
+            .map(|(_, values)| self.presses(&values).unwrap()))
+    }
+
+    /**
+     * None when a pivot button would need a negative, fractional or over the cap number of presses
+     */
+    fn presses(&self, free_values: &[i64]) -> Option<Presses> {
+        let mut per_button = vec![0; self.bounds.len()];
+        for (&button, &value) in self.free_buttons.iter().zip(free_values) {
+            per_button[button] = u32::try_from(value).ok()?;
+        }
+        for row in &self.rows {
+            let numerator = i128::from(row.rhs)
+                - row
+                    .coefficients
+                    .iter()
+                    .zip(free_values)
+                    .map(|(&c, &v)| i128::from(c) * i128::from(v))
+                    .sum::<i128>();
+            let scale = i128::from(row.scale);
+            if numerator < 0
+                || numerator % scale != 0
+                || numerator / scale > i128::from(self.bounds[row.pivot])
+            {
+                return None;
+            }
+            per_button[row.pivot] = u32::try_from(numerator / scale).ok()?;
+        }
+        Some(Presses { per_button })
+    }
+}
+
+/**
+ * Depth-first over the free buttons, pruning on the best reachable cost
+ * and on rows that can no longer keep their pivot within its bounds
+ */
+struct FreeButtonSearch<'a> {
+    system: &'a ReducedSystem,
+    weights: Vec<i128>,
+    values: Vec<i64>,
+    best: Option<(i128, Vec<i64>)>,
+}
+
+impl FreeButtonSearch<'_> {
+    fn run(&mut self, scaled_cost: i128) {
+        let depth = self.values.len();
+        let free_buttons = &self.system.free_buttons;
+
+        if depth == free_buttons.len() {
+            if self
+                .best
+                .as_ref()
+                .is_none_or(|(best, _)| scaled_cost < *best)
+                && self.system.presses(&self.values).is_some()
+            {
+                self.best = Some((scaled_cost, self.values.clone()));
+            }
+            return;
+        }
+
+        let remaining = depth..free_buttons.len();
+        let lowest_remaining_cost: i128 = remaining
+            .clone()
+            .map(|f| self.weights[f].min(0) * i128::from(self.system.bounds[free_buttons[f]]))
+            .sum();
+        if self
+            .best
+            .as_ref()
+            .is_some_and(|(best, _)| scaled_cost + lowest_remaining_cost >= *best)
+        {
+            return;
+        }
+        let feasible = self.system.rows.iter().all(|row| {
+            let assigned: i128 = row.coefficients[..depth]
+                .iter()
+                .zip(&self.values)
+                .map(|(&c, &v)| i128::from(c) * i128::from(v))
+                .sum();
+            let (largest_remaining, smallest_remaining) =
+                remaining.clone().fold((0, 0), |(largest, smallest), f| {
+                    let reach = i128::from(row.coefficients[f])
+                        * i128::from(self.system.bounds[free_buttons[f]]);
+                    (largest - reach.min(0), smallest - reach.max(0))
+                });
+            let rhs = i128::from(row.rhs);
+            rhs - assigned + largest_remaining >= 0
+                && rhs - assigned + smallest_remaining
+                    <= i128::from(self.system.bounds[row.pivot]) * i128::from(row.scale)
+        });
+        if !feasible {
+            return;
+        }
+
+        for value in 0..=self.system.bounds[free_buttons[depth]] {
+            self.values.push(value);
+            self.run(scaled_cost + self.weights[depth] * i128::from(value));
+            self.values.pop();
+        }
+    }
+}
+
+/**
+ * None on overflow
+ */
+fn lcm(a: i64, b: i64) -> Option<i64> {
+    (a / gcd(a, b)).checked_mul(b)
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 { a.abs() } else { gcd(b, a % b) }
+}
+
+impl Machine {
+    /**
+     * `[.##.] (3) (1,3)$2 (2)<=4 {3,5,4}`: indicator lights, buttons with the lights they are wired to,
+     * then one joltage counter per light
+     * A button may be followed by its cost `$c` and its maximum number of presses `<=m`
+     */
+    fn parse(line: usize, s: &str) -> Result<Self, MachineError> {
         let parts = s.split_whitespace().collect::<Vec<&str>>();
+        let invalid_lights = |value: &str| MachineError::InvalidLights {
+            line,
+            value: value.to_string(),
+        };
 
-        let indicator_lights = parts
-            .first()
-            .unwrap()
-            .trim_start_matches('[')
-            .trim_end_matches(']')
+        let lights_part = parts.first().copied().unwrap_or_default();
+        let indicator_lights = lights_part
+            .strip_prefix('[')
+            .and_then(|lights| lights.strip_suffix(']'))
+            .ok_or_else(|| invalid_lights(lights_part))?
             .chars()
-            .filter_map(|c| match c {
-                '.' => Some(false),
-                '#' => Some(true),
-                _ => None,
+            .map(|c| match c {
+                '.' => Ok(false),
+                '#' => Ok(true),
+                _ => Err(invalid_lights(lights_part)),
             })
-            .collect::<Vec<bool>>();
-
-        let mut joltage_requirements = [0; MAX_JOLTAGES];
-        parts
-            .last()
-            .unwrap()
-            .trim_start_matches('{')
-            .trim_end_matches('}')
+            .collect::<Result<Vec<bool>, _>>()?;
+
+        let joltages_part = parts.last().copied().unwrap_or_default();
+        let invalid_joltages = || MachineError::InvalidJoltages {
+            line,
+            value: joltages_part.to_string(),
+        };
+        let joltage_requirements = joltages_part
+            .strip_prefix('{')
+            .and_then(|joltages| joltages.strip_suffix('}'))
+            .filter(|_| parts.len() >= 2)
+            .ok_or_else(invalid_joltages)?
             .split(',')
-            .filter_map(|num_str| num_str.parse::<Joltage>().ok())
-            .take(10)
-            .enumerate()
-            .for_each(|(index, joltage)| {
-                joltage_requirements[index] = joltage;
+            .map(|num_str| num_str.parse::<Joltage>().map_err(|_| invalid_joltages()))
+            .collect::<Result<Vec<Joltage>, _>>()?;
+        if joltage_requirements.len() != indicator_lights.len() {
+            return Err(MachineError::CounterMismatch {
+                line,
+                lights: indicator_lights.len(),
+                counters: joltage_requirements.len(),
             });
+        }
 
-        let buttons = parts
-            .iter()
-            .skip(1)
-            .take(parts.len() - 2)
-            .map(|btn_str| {
-                btn_str
-                    .trim_start_matches('(')
-                    .trim_end_matches(')')
-                    .split(',')
-                    .filter_map(|idx_str| idx_str.parse::<usize>().ok())
-                    .collect::<Vec<usize>>()
-            })
-            .collect::<Vec<Vec<usize>>>();
+        let mut buttons = Vec::new();
+        let mut costs = Vec::new();
+        let mut caps = Vec::new();
+        for btn_str in &parts[1..parts.len() - 1] {
+            let invalid_button = || MachineError::InvalidButton {
+                line,
+                value: (*btn_str).to_string(),
+            };
+            let (indices, options) = btn_str
+                .strip_prefix('(')
+                .and_then(|button| button.split_once(')'))
+                .ok_or_else(invalid_button)?;
+            let button = indices
+                .split(',')
+                .map(|idx_str| {
+                    let index = idx_str.parse::<usize>().map_err(|_| invalid_button())?;
+                    if index >= indicator_lights.len() {
+                        return Err(MachineError::ButtonOutOfRange {
+                            line,
+                            index,
+                            lights: indicator_lights.len(),
+                        });
+                    }
+                    Ok(index)
+                })
+                .collect::<Result<Vec<usize>, _>>()?;
+
+            let (cost, cap) = match options.split_once("<=") {
+                Some((cost, cap)) => (cost, Some(cap)),
+                None => (options, None),
+            };
+            let cost = match cost {
+                "" => 1,
+                cost => cost
+                    .strip_prefix('$')
+                    .and_then(|cost| cost.parse().ok())
+                    .ok_or_else(invalid_button)?,
+            };
+            let cap = cap
+                .map(|cap| cap.parse().map_err(|_| invalid_button()))
+                .transpose()?;
 
-        Machine {
+            buttons.push(button);
+            costs.push(cost);
+            caps.push(cap);
+        }
+
+        Ok(Machine {
+            line,
             indicator_lights,
             buttons,
+            costs,
+            caps,
             joltage_requirements,
-        }
+        })
     }
 }
 
@@ -256,27 +968,63 @@ mod test {
     #[test]
     fn p1_configure_lights_0() {
         let machine =
-            Machine::from(&"[.##.] (3) (1,3) (2) (2,3) (0,2) (0,1) {3,5,4,7}".to_string());
+            Machine::parse(1, "[.##.] (3) (1,3) (2) (2,3) (0,2) (0,1) {3,5,4,7}").unwrap();
 
-        assert_eq!(machine.configure_lights(), 2);
+        assert_eq!(
+            machine.configure_lights().map(|s| s.presses.total()),
+            Some(2)
+        );
     }
 
     #[test]
-    fn p1_configure_lights_1() {
-        let machine = Machine::from(
-            &"[...#.] (0,2,3,4) (2,3) (0,4) (0,1,2) (1,2,3,4) {7,5,12,7,2}".to_string(),
+    fn p1_configure_lights_optimal_solutions() {
+        let machine =
+            Machine::parse(1, "[.##.] (3) (1,3) (2) (2,3) (0,2) (0,1) {3,5,4,7}").unwrap();
+
+        // (1,3) (2,3) or (0,2) (0,1)
+        assert_eq!(
+            machine.configure_lights().map(|s| s.optimal_solutions),
+            Some(2)
         );
+    }
+
+    #[test]
+    fn p1_configure_lights_unsolvable() {
+        let machine = Machine::parse(1, "[#..] (0,1) (1,2) {1,1,1}").unwrap();
 
-        assert_eq!(machine.configure_lights(), 3);
+        assert!(machine.configure_lights().is_none());
+        assert_eq!(
+            p1(&["[#..] (0,1) (1,2) {1,1,1}".to_string()]),
+            Err(MachineError::UnreachableLights { line: 1 })
+        );
     }
 
     #[test]
-    fn p1_configure_lights_2() {
-        let machine = Machine::from(
-            &"[.###.#] (0,1,2,3,4) (0,3,4) (0,1,2,4,5) (1,2) {10,11,11,5,10,5}".to_string(),
+    fn p1_configure_lights_1() {
+        let machine = Machine::parse(
+            1,
+            "[...#.] (0,2,3,4) (2,3) (0,4) (0,1,2) (1,2,3,4) {7,5,12,7,2}",
+        )
+        .unwrap();
+
+        assert_eq!(
+            machine.configure_lights().map(|s| s.presses.total()),
+            Some(3)
         );
+    }
 
-        assert_eq!(machine.configure_lights(), 2);
+    #[test]
+    fn p1_configure_lights_2() {
+        let machine = Machine::parse(
+            1,
+            "[.###.#] (0,1,2,3,4) (0,3,4) (0,1,2,4,5) (1,2) {10,11,11,5,10,5}",
+        )
+        .unwrap();
+
+        assert_eq!(
+            machine.configure_lights().map(|s| s.presses.total()),
+            Some(2)
+        );
     }
 
     #[test]
@@ -289,31 +1037,258 @@ mod test {
 
         let result = p1(&input);
 
-        assert_eq!(result, 7);
+        assert_eq!(result, Ok(7));
+    }
+
+    #[test]
+    fn p2_configure_joltages_0() {
+        let machine =
+            Machine::parse(1, "[.##.] (3) (1,3) (2) (2,3) (0,2) (0,1) {3,5,4,7}").unwrap();
+
+        assert_eq!(
+            machine.configure_joltages().unwrap().map(|p| p.total()),
+            Some(10)
+        );
+    }
+
+    #[test]
+    fn p2_configure_joltages_2() {
+        let machine = Machine::parse(
+            1,
+            "[.###.#] (0,1,2,3,4) (0,3,4) (0,1,2,4,5) (1,2) {10,11,11,5,10,5}",
+        )
+        .unwrap();
+
+        assert_eq!(
+            machine.configure_joltages().unwrap(),
+            Some(Presses {
+                per_button: vec![5, 0, 5, 1]
+            })
+        );
+    }
+
+    #[test]
+    fn plans_are_verified() {
+        let input = vec![
+            "[.##.] (3) (1,3) (2) (2,3) (0,2) (0,1) {3,5,4,7}".to_string(),
+            "[...#.] (0,2,3,4) (2,3) (0,4) (0,1,2) (1,2,3,4) {7,5,12,7,2}".to_string(),
+            "[.###.#] (0,1,2,3,4) (0,3,4) (0,1,2,4,5) (1,2) {10,11,11,5,10,5}".to_string(),
+        ];
+
+        for line in &input {
+            let machine = Machine::parse(1, line).unwrap();
+            assert!(machine.verify_lights(&machine.configure_lights().unwrap().presses));
+            assert!(machine.verify_joltages(&machine.configure_joltages().unwrap().unwrap()));
+        }
+    }
+
+    #[test]
+    fn plans_mismatch() {
+        let machine =
+            Machine::parse(1, "[.##.] (3) (1,3) (2) (2,3) (0,2) (0,1) {3,5,4,7}").unwrap();
+        let presses = Presses {
+            per_button: vec![0, 1, 0, 0, 0, 1],
+        };
+
+        assert!(!machine.verify_lights(&presses));
+        assert!(!machine.verify_joltages(&presses));
+        assert!(!machine.verify_lights(&Presses { per_button: vec![] }));
+    }
+
+    #[test]
+    fn many_lights_and_counters() {
+        let lights = ".#".repeat(20);
+        let odd_lights: Vec<String> = (1..40).step_by(2).map(|i| i.to_string()).collect();
+        let mut buttons: Vec<String> = (0..40).map(|i| format!("({i})")).collect();
+        buttons.push(format!("({})", odd_lights.join(",")));
+        let joltages: Vec<String> = (0..40).map(|i| (i % 3).to_string()).collect();
+        let line = format!(
+            "[{lights}] {} {{{}}}",
+            buttons.join(" "),
+            joltages.join(",")
+        );
+        let machine = Machine::parse(1, &line).unwrap();
+
+        assert_eq!(
+            machine.configure_lights().map(|s| s.presses.total()),
+            Some(1)
+        );
+        assert_eq!(
+            machine.configure_joltages().unwrap().map(|p| p.total()),
+            Some(39)
+        );
+    }
+
+    #[test]
+    fn many_free_buttons() {
+        let buttons = ["(0)"; 35].join(" ") + " " + &["(1)"; 35].join(" ");
+        let line = format!("[##] {buttons} {{1,1}}");
+        let machine = Machine::parse(1, &line).unwrap();
+
+        let lights = machine.configure_lights().unwrap();
+        assert_eq!(lights.presses.total(), 2);
+        assert_eq!(lights.optimal_solutions, 35 * 35);
+        assert!(machine.verify_lights(&lights.presses));
+        assert_eq!(p1(&[line]), Ok(2));
+    }
+
+    #[test]
+    fn button_costs_and_caps() {
+        let expensive =
+            Machine::parse(1, "[.##.] (3) (1,3)$5 (2) (2,3) (0,2) (0,1) {3,5,4,7}").unwrap();
+        let lights = expensive.configure_lights().unwrap();
+        assert_eq!(expensive.cost(&lights.presses), Some(2));
+        assert_eq!(lights.optimal_solutions, 1);
+
+        let capped =
+            Machine::parse(1, "[.##.] (3) (1,3)$5 (2) (2,3) (0,2)<=0 (0,1) {3,5,4,7}").unwrap();
+        let lights = capped.configure_lights().unwrap();
+        assert_eq!(capped.cost(&lights.presses), Some(6));
+        assert!(capped.verify_lights(&lights.presses));
+
+        let joltages = |line: &str| {
+            let machine = Machine::parse(1, line).unwrap();
+            machine
+                .configure_joltages()
+                .unwrap()
+                .and_then(|presses| machine.cost(&presses))
+        };
+        assert_eq!(joltages("[..] (0) (1) (0,1)$3 {2,2}"), Some(4));
+        assert_eq!(joltages("[..] (0) (1) (0,1) {2,2}"), Some(2));
+        assert_eq!(joltages("[..] (0)<=1 (1) (0,1)$3 {2,2}"), Some(5));
+        assert_eq!(joltages("[..] (0)<=1 (1)<=1 (0,1)<=1 {2,2}"), Some(3));
+        assert_eq!(joltages("[..] (0)<=1 (1)<=1 (0,1)<=1 {3,3}"), None);
+    }
+
+    #[test]
+    fn cost_overflow() {
+        let machine = Machine::parse(
+            1,
+            "[..] (0)$4294967295 (1)$4294967295 (0,1)$4294967295 {1,1}",
+        )
+        .unwrap();
+
+        let presses = |per_button: Vec<u32>| Presses { per_button };
+        assert_eq!(
+            machine.cost(&presses(vec![u32::MAX, 1, 0])),
+            Some(u64::from(u32::MAX) * u64::from(u32::MAX) + u64::from(u32::MAX))
+        );
+        assert_eq!(machine.cost(&presses(vec![u32::MAX; 3])), None);
+        assert_eq!(
+            p1(&["[#.] (0)$4294967295 (1) {1,1}".to_string()]),
+            Ok(u64::from(u32::MAX))
+        );
+    }
+
+    #[test]
+    fn scaled_cost_overflow() {
+        let system = |scales: &[i64]| ReducedSystem {
+            line: 3,
+            rows: scales
+                .iter()
+                .enumerate()
+                .map(|(pivot, &scale)| ReducedRow {
+                    pivot,
+                    scale,
+                    rhs: scale,
+                    coefficients: vec![],
+                })
+                .collect(),
+            free_buttons: vec![],
+            bounds: vec![1; scales.len()],
+            costs: vec![i64::from(u32::MAX); scales.len()],
+        };
+
+        assert_eq!(
+            system(&[1 << 20])
+                .minimum_presses()
+                .map(|p| p.map(|p| p.total())),
+            Ok(Some(1))
+        );
+        assert_eq!(
+            system(&[1 << 40]).minimum_presses(),
+            Err(MachineError::CostOverflow { line: 3 })
+        );
+        assert_eq!(
+            system(&[1 << 40, (1 << 40) - 1]).minimum_presses(),
+            Err(MachineError::CostOverflow { line: 3 })
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            p1(&["[.##.] (3) (1,4) {3,5,4,7}".to_string()]),
+            Err(MachineError::ButtonOutOfRange {
+                line: 1,
+                index: 4,
+                lights: 4
+            })
+        );
+        assert_eq!(
+            Machine::parse(2, "[.##.] (3) {3,5,4}").err(),
+            Some(MachineError::CounterMismatch {
+                line: 2,
+                lights: 4,
+                counters: 3
+            })
+        );
+        assert_eq!(
+            Machine::parse(3, "[.x] (0) {1,1}").err(),
+            Some(MachineError::InvalidLights {
+                line: 3,
+                value: "[.x]".to_string()
+            })
+        );
+        assert_eq!(
+            Machine::parse(4, "[.#] (0)$x {1,1}").err(),
+            Some(MachineError::InvalidButton {
+                line: 4,
+                value: "(0)$x".to_string()
+            })
+        );
+        assert_eq!(
+            Machine::parse(4, "[.#] (0;1) {1,1}").err(),
+            Some(MachineError::InvalidButton {
+                line: 4,
+                value: "(0;1)".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn p2_configure_joltages_unreachable() {
+        let machine = Machine::parse(1, "[..] (0,1) {1,2}").unwrap();
+
+        assert_eq!(machine.configure_joltages(), Ok(None));
     }
 
     #[test]
     fn p2_configure_joltages_a_star_0() {
         let machine =
-            Machine::from(&"[.##.] (3) (1,3) (2) (2,3) (0,2) (0,1) {3,5,4,7}".to_string());
+            Machine::parse(1, "[.##.] (3) (1,3) (2) (2,3) (0,2) (0,1) {3,5,4,7}").unwrap();
 
         assert_eq!(machine.configure_joltages_a_star(), 10);
     }
 
     #[test]
     fn p2_configure_joltages_a_star_1() {
-        let machine = Machine::from(
-            &"[...#.] (0,2,3,4) (2,3) (0,4) (0,1,2) (1,2,3,4) {7,5,12,7,2}".to_string(),
-        );
+        let machine = Machine::parse(
+            1,
+            "[...#.] (0,2,3,4) (2,3) (0,4) (0,1,2) (1,2,3,4) {7,5,12,7,2}",
+        )
+        .unwrap();
 
         assert_eq!(machine.configure_joltages_a_star(), 12);
     }
 
     #[test]
     fn p2_configure_joltages_a_star_2() {
-        let machine = Machine::from(
-            &"[.###.#] (0,1,2,3,4) (0,3,4) (0,1,2,4,5) (1,2) {10,11,11,5,10,5}".to_string(),
-        );
+        let machine = Machine::parse(
+            1,
+            "[.###.#] (0,1,2,3,4) (0,3,4) (0,1,2,4,5) (1,2) {10,11,11,5,10,5}",
+        )
+        .unwrap();
 
         assert_eq!(machine.configure_joltages_a_star(), 11);
     }
@@ -328,6 +1303,6 @@ mod test {
 
         let result = p2(&input);
 
-        assert_eq!(result, 33);
+        assert_eq!(result, Ok(33));
     }
 }
@@ -1,20 +1,80 @@
 use std::{
-    collections::HashMap,
-    fs::File,
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+    env,
+    error::Error,
+    fmt::{self, Write as _},
+    fs::{self, File},
     io::{self, BufRead, BufReader},
+    mem,
+    num::NonZeroU64,
 };
 
 fn main() -> io::Result<()> {
     let input = read_input()?;
+    let args: Vec<String> = env::args().collect();
+    let rack = ServerRack::try_from(input.as_slice()).map_err(invalid_data)?;
+    let start = option_value(&args, "--from").unwrap_or(YOU);
+    let end = option_value(&args, "--to").unwrap_or(END);
 
-    let p1_result = p1(&input);
+    if args.iter().any(|arg| arg == "--diagnose") {
+        print!("{}", rack.diagnose(start, end));
+    }
+
+    let p1_result = p1(&input).map_err(invalid_data)?;
 
     println!("Part 1 result: {p1_result}");
 
-    let p2_result = p2(&input);
+    let p2_result = p2(&input).map_err(invalid_data)?;
 
     println!("Part 2 result: {p2_result}");
 
+    if let Some(limit) = option_value(&args, "--paths") {
+        let limit = limit.parse().map_err(invalid_number)?;
+        for path in rack.paths_from(start, end).take(limit) {
+            println!("{}", path.join(" -> "));
+        }
+    }
+
+    if let Some(k) = option_value(&args, "--shortest") {
+        let k = k.parse().map_err(invalid_number)?;
+        for path in rack.k_shortest_paths(start, end, k) {
+            println!("{}: {}", path.len() - 1, path.join(" -> "));
+        }
+    }
+
+    let through = option_value(&args, "--through");
+    let avoid = option_value(&args, "--avoid");
+    let before = option_value(&args, "--before");
+    let dot_path = option_value(&args, "--dot");
+    if through.is_some() || avoid.is_some() || before.is_some() || dot_path.is_some() {
+        let query = PathQuery {
+            required: through.map_or_else(Vec::new, |devices| devices.split(',').collect()),
+            forbidden: avoid.map_or_else(Vec::new, |devices| devices.split(',').collect()),
+            orderings: before
+                .map_or_else(Vec::new, |pairs| pairs.split(',').collect())
+                .into_iter()
+                .map(|pair| {
+                    pair.split_once(':')
+                        .ok_or(RackError::InvalidOrdering(pair.to_string()))
+                })
+                .collect::<Result<_, _>>()
+                .map_err(invalid_data)?,
+            ..PathQuery::new(start, end)
+        };
+        let mode = match option_value(&args, "--modulo") {
+            Some(modulus) => CountMode::Modulo(modulus.parse().map_err(invalid_number)?),
+            None if args.iter().any(|arg| arg == "--wide") => CountMode::Wide,
+            None => CountMode::Checked,
+        };
+        let paths = rack.count_paths(&query, mode).map_err(invalid_data)?;
+        println!("{query}: {paths}");
+
+        if let Some(path) = dot_path {
+            fs::write(path, rack.to_dot(&query, mode).map_err(invalid_data)?)?;
+        }
+    }
+
     Ok(())
 }
 
@@ -23,79 +83,896 @@ fn read_input() -> io::Result<Vec<String>> {
     BufReader::new(file).lines().collect::<Result<_, _>>()
 }
 
+fn invalid_data(error: RackError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn invalid_number(error: std::num::ParseIntError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, error)
+}
+
+fn option_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
 const YOU: &str = "you";
 const SVR: &str = "svr";
 const DAC: &str = "dac";
 const FFT: &str = "fft";
 const END: &str = "out";
 
-type Mask = u8;
-const NO_MASK: Mask = 0;
-const DAC_MASK: Mask = 1;
-const FFT_MASK: Mask = 2;
+fn p1(input: &[String]) -> Result<u128, RackError> {
+    ServerRack::try_from(input)?.count_paths(&PathQuery::new(YOU, END), CountMode::Checked)
+}
+
+fn p2(input: &[String]) -> Result<u128, RackError> {
+    ServerRack::try_from(input)?.count_paths(
+        &PathQuery {
+            required: vec![DAC, FFT],
+            ..PathQuery::new(SVR, END)
+        },
+        CountMode::Checked,
+    )
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum RackError {
+    MissingSeparator { line: usize },
+    CountOverflow,
+    InvalidOrdering(String),
+    UnknownWaypoint(String),
+    Cycle(Vec<String>),
+}
+
+impl fmt::Display for RackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RackError::MissingSeparator { line } => {
+                write!(f, "line {line} should look like \"device: output output\"")
+            }
+            RackError::CountOverflow => write!(f, "path count overflow"),
+            RackError::InvalidOrdering(value) => {
+                write!(
+                    f,
+                    "ordering {value:?} should look like \"before:after\" with two different devices"
+                )
+            }
+            RackError::UnknownWaypoint(device) => {
+                write!(f, "ordering on {device:?} which is not a required device")
+            }
+            RackError::Cycle(devices) => write!(f, "cycle {}", devices.join(" -> ")),
+        }
+    }
+}
+
+impl Error for RackError {}
+
+/**
+ * Paths from `start` to `end` visiting every required device and none of the forbidden ones,
+ * each ordering `(before, after)` asks for `before` to be visited ahead of `after`
+ */
+struct PathQuery<'a> {
+    start: &'a str,
+    end: &'a str,
+    required: Vec<&'a str>,
+    forbidden: Vec<&'a str>,
+    orderings: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> PathQuery<'a> {
+    fn new(start: &'a str, end: &'a str) -> Self {
+        PathQuery {
+            start,
+            end,
+            required: Vec::new(),
+            forbidden: Vec::new(),
+            orderings: Vec::new(),
+        }
+    }
 
-fn p1(input: &[String]) -> usize {
-    ServerRack::from(input).count_paths_from(YOU, NO_MASK, NO_MASK, &mut HashMap::new())
+    /**
+     * Orderings as pairs of indices in the required list, a device can't come before itself
+     */
+    fn ordering_indices(&self) -> Result<Vec<(usize, usize)>, RackError> {
+        let index = |device: &str| {
+            self.required
+                .iter()
+                .position(|&waypoint| waypoint == device)
+                .ok_or_else(|| RackError::UnknownWaypoint(device.to_string()))
+        };
+        self.orderings
+            .iter()
+            .map(|&(before, after)| {
+                if before == after {
+                    return Err(RackError::InvalidOrdering(format!("{before}:{after}")));
+                }
+                Ok((index(before)?, index(after)?))
+            })
+            .collect()
+    }
 }
 
-fn p2(input: &[String]) -> usize {
-    ServerRack::from(input).count_paths_from(SVR, DAC_MASK | FFT_MASK, NO_MASK, &mut HashMap::new())
+/**
+ * How path counts are added up: checked against `u64` or `u128` overflow, or modulo some number
+ */
+#[derive(Debug, Clone, Copy)]
+enum CountMode {
+    Checked,
+    Wide,
+    Modulo(NonZeroU64),
 }
 
-fn apply_mask(current_mask: Mask, device: &str) -> Mask {
-    match device {
-        DAC => current_mask | DAC_MASK,
-        FFT => current_mask | FFT_MASK,
-        _ => current_mask,
+impl CountMode {
+    fn one(self) -> u128 {
+        match self {
+            CountMode::Modulo(modulus) => 1 % u128::from(modulus.get()),
+            _ => 1,
+        }
+    }
+
+    fn add(self, a: u128, b: u128) -> Result<u128, RackError> {
+        match self {
+            CountMode::Checked => a.checked_add(b).filter(|&sum| sum <= u128::from(u64::MAX)),
+            CountMode::Wide => a.checked_add(b),
+            CountMode::Modulo(modulus) => Some((a + b) % u128::from(modulus.get())),
+        }
+        .ok_or(RackError::CountOverflow)
+    }
+}
+
+impl fmt::Display for PathQuery<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Paths from {} to {}", self.start, self.end)?;
+        if !self.required.is_empty() {
+            write!(f, " through {}", self.required.join(", "))?;
+        }
+        if !self.forbidden.is_empty() {
+            write!(f, " avoiding {}", self.forbidden.join(", "))?;
+        }
+        for (before, after) in &self.orderings {
+            write!(f, ", {before} before {after}")?;
+        }
+        Ok(())
     }
 }
 
 struct ServerRack<'a> {
     device_connections: HashMap<&'a str, Vec<&'a str>>,
+    /** Devices defined on several lines, the last definition wins */
+    duplicates: Vec<DuplicateDefinition<'a>>,
 }
 
-impl<'a> From<&'a [String]> for ServerRack<'a> {
-    fn from(lines: &'a [String]) -> Self {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DuplicateDefinition<'a> {
+    device: &'a str,
+    first_line: usize,
+    line: usize,
+}
+
+impl<'a> TryFrom<&'a [String]> for ServerRack<'a> {
+    type Error = RackError;
+
+    fn try_from(lines: &'a [String]) -> Result<Self, Self::Error> {
         let mut device_connections = HashMap::new();
-        for line in lines {
-            let parts: Vec<&str> = line.split(": ").collect();
-            let device_name = parts[0];
-            let connections: Vec<&str> = parts[1].split(' ').collect();
+        let mut definition_lines = HashMap::new();
+        let mut duplicates = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            let (device_name, connections) = line
+                .split_once(": ")
+                .ok_or(RackError::MissingSeparator { line: i + 1 })?;
+            let connections: Vec<&str> = connections.split_whitespace().collect();
             device_connections.insert(device_name, connections);
+
+            if let Some(&first_line) = definition_lines.get(device_name) {
+                duplicates.push(DuplicateDefinition {
+                    device: device_name,
+                    first_line,
+                    line: i + 1,
+                });
+            } else {
+                definition_lines.insert(device_name, i + 1);
+            }
         }
 
-        ServerRack { device_connections }
+        Ok(ServerRack {
+            device_connections,
+            duplicates,
+        })
     }
 }
 
 impl<'a> ServerRack<'a> {
-    fn count_paths_from(
-        &self,
-        device: &'a str,
-        mask: Mask,
-        target_mask: Mask,
-        visited: &mut HashMap<(&'a str, Mask), usize>,
-    ) -> usize {
-        if device == END {
-            return usize::from((mask & target_mask) == target_mask);
+    fn connections(&self, device: &str) -> &[&'a str] {
+        self.device_connections
+            .get(device)
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /**
+     * Number of paths matching the query, on the interned rack
+     */
+    fn count_paths(&self, query: &PathQuery<'a>, mode: CountMode) -> Result<u128, RackError> {
+        InternedRack::from(self).count_paths(query, mode)
+    }
+
+    /**
+     * Graphviz DOT source of the rack annotated with path counts for the query
+     */
+    fn to_dot(&self, query: &PathQuery<'a>, mode: CountMode) -> Result<String, RackError> {
+        InternedRack::from(self).to_dot(query, mode)
+    }
+
+    /**
+     * Lazily enumerates the paths from `start` to `end`, depth first, never revisiting a device
+     */
+    fn paths_from(&self, start: &'a str, end: &'a str) -> Paths<'_, 'a> {
+        Paths {
+            rack: self,
+            end,
+            stack: vec![(start, 0)],
+        }
+    }
+
+    /**
+     * The `k` paths from `start` to `end` with the fewest connections, ties broken by device names
+     * Best-first on partial paths, the exact distance to `end` as heuristic pops them in order
+     */
+    fn k_shortest_paths(&self, start: &'a str, end: &'a str, k: usize) -> Vec<Vec<&'a str>> {
+        let distances = self.distances_to(end);
+        let mut shortest_paths = Vec::new();
+        let mut open_set = BinaryHeap::new(); // Min-heap using Reverse on BinaryHeap
+
+        if let Some(&distance) = distances.get(start) {
+            open_set.push(Reverse((distance, vec![start])));
+        }
+
+        while let Some(Reverse((_, path))) = open_set.pop() {
+            if shortest_paths.len() == k {
+                break;
+            }
+
+            let device = path[path.len() - 1];
+            if device == end {
+                shortest_paths.push(path);
+                continue;
+            }
+
+            for &child in self.connections(device) {
+                if let Some(&distance) = distances.get(child)
+                    && !path.contains(&child)
+                {
+                    let mut new_path = path.clone();
+                    new_path.push(child);
+                    open_set.push(Reverse((new_path.len() - 1 + distance, new_path)));
+                }
+            }
+        }
+
+        shortest_paths
+    }
+
+    /**
+     * Every device, either defined or only connected to, by name
+     */
+    fn devices(&self) -> Vec<&'a str> {
+        let mut devices: Vec<&str> = self
+            .device_connections
+            .iter()
+            .flat_map(|(&device, connections)| connections.iter().copied().chain([device]))
+            .collect();
+        devices.sort_unstable();
+        devices.dedup();
+        devices
+    }
+
+    /**
+     * Iterative DFS from each root, each connection back to a device of the current path
+     * closes a cycle, reported from that device back to itself
+     */
+    fn cycles_from(&self, roots: &[&'a str], first_only: bool) -> Vec<Vec<&'a str>> {
+        let mut done: HashMap<&str, bool> = HashMap::new(); // false while on the current path
+        let mut cycles = Vec::new();
+
+        for &root in roots {
+            if done.contains_key(root) {
+                continue;
+            }
+            done.insert(root, false);
+            let mut stack = vec![(root, 0)];
+
+            while let Some(&(device, next_connection)) = stack.last() {
+                let Some(&child) = self.connections(device).get(next_connection) else {
+                    done.insert(device, true);
+                    stack.pop();
+                    continue;
+                };
+                let last = stack.len() - 1;
+                stack[last].1 += 1;
+
+                match done.get(child) {
+                    None => {
+                        done.insert(child, false);
+                        stack.push((child, 0));
+                    }
+                    Some(false) => {
+                        let from = stack.iter().position(|&(d, _)| d == child).unwrap();
+                        let mut cycle: Vec<&str> = stack[from..].iter().map(|&(d, _)| d).collect();
+                        cycle.push(child);
+                        cycles.push(cycle);
+                        if first_only {
+                            return cycles;
+                        }
+                    }
+                    Some(true) => {}
+                }
+            }
+        }
+
+        cycles
+    }
+
+    fn diagnose(&self, start: &'a str, end: &'a str) -> Diagnostics<'a> {
+        let devices = self.devices();
+
+        let mut reachable = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(device) = queue.pop_front() {
+            for &child in self.connections(device) {
+                if reachable.insert(child) {
+                    queue.push_back(child);
+                }
+            }
         }
+        let distances = self.distances_to(end);
+
+        Diagnostics {
+            start,
+            end,
+            cycles: self.cycles_from(&devices, false),
+            unreachable: devices
+                .iter()
+                .filter(|device| !reachable.contains(*device))
+                .copied()
+                .collect(),
+            dead_ends: devices
+                .iter()
+                .filter(|device| !distances.contains_key(*device))
+                .copied()
+                .collect(),
+            duplicates: self.duplicates.clone(),
+        }
+    }
+
+    /**
+     * BFS on the reversed connections, devices that can't reach `end` are absent
+     */
+    fn distances_to(&self, end: &'a str) -> HashMap<&'a str, usize> {
+        let mut reversed: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (&device, connections) in &self.device_connections {
+            for &child in connections {
+                reversed.entry(child).or_default().push(device);
+            }
+        }
+
+        let mut distances = HashMap::from([(end, 0)]);
+        let mut queue = VecDeque::from([end]);
+        while let Some(device) = queue.pop_front() {
+            let distance = distances[device];
+            for &parent in reversed.get(device).map_or(&[][..], Vec::as_slice) {
+                distances.entry(parent).or_insert_with(|| {
+                    queue.push_back(parent);
+                    distance + 1
+                });
+            }
+        }
+
+        distances
+    }
+}
+
+/**
+ * Devices interned to their index in `names`, with connections by index
+ */
+struct InternedRack<'a> {
+    names: Vec<&'a str>,
+    ids: HashMap<&'a str, usize>,
+    connections: Vec<Vec<usize>>,
+}
+
+impl<'a> From<&ServerRack<'a>> for InternedRack<'a> {
+    fn from(rack: &ServerRack<'a>) -> Self {
+        let names = rack.devices();
+        let ids: HashMap<&str, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(id, &name)| (name, id))
+            .collect();
+        let connections = names
+            .iter()
+            .map(|&name| {
+                rack.connections(name)
+                    .iter()
+                    .map(|child| ids[child])
+                    .collect()
+            })
+            .collect();
 
-        let state = (device, mask);
-        if let Some(&result) = visited.get(&state) {
-            return result;
+        InternedRack {
+            names,
+            ids,
+            connections,
         }
+    }
+}
 
-        let next_mask = apply_mask(mask, device);
+impl InternedRack<'_> {
+    /**
+     * In a DAG a path meets its required devices in topological order, so the mask of those
+     * visited is a prefix of them sorted that way: counts are propagated in topological order
+     * for each prefix length, each device only holding the prefix lengths that reach it until
+     * it is processed
+     */
+    fn count_paths(&self, query: &PathQuery, mode: CountMode) -> Result<u128, RackError> {
+        let Some(staging) = self.staging(query)? else {
+            return Ok(0);
+        };
+
+        let mut counts: Vec<Vec<(usize, u128)>> = vec![Vec::new(); self.names.len()];
+        if let Some(stage) = staging.enter(staging.start, 0) {
+            counts[staging.start].push((stage, mode.one()));
+        }
 
         let mut paths = 0;
-        if let Some(children) = self.device_connections.get(device) {
-            for &child in children {
-                paths += self.count_paths_from(child, next_mask, target_mask, visited);
+        for &device in &staging.order {
+            for (stage, count) in mem::take(&mut counts[device]) {
+                if device == staging.end {
+                    if stage == staging.waypoints {
+                        paths = mode.add(paths, count)?;
+                    }
+                    continue;
+                }
+                for child in self.children(device, staging.end, &staging.forbidden) {
+                    if let Some(next_stage) = staging.enter(child, stage) {
+                        let child_counts = &mut counts[child];
+                        match child_counts.iter_mut().find(|(s, _)| *s == next_stage) {
+                            Some((_, slot)) => *slot = mode.add(*slot, count)?,
+                            None => child_counts.push((next_stage, count)),
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(paths)
+    }
+
+    /**
+     * Devices reachable from the start in topological order, and the stage reached when entering
+     * each of them, None when the query can't have any path
+     */
+    fn staging(&self, query: &PathQuery) -> Result<Option<Staging>, RackError> {
+        let orderings = query.ordering_indices()?;
+        let forbidden = self.forbidden(query);
+        let (Some(&start), Some(&end)) = (self.ids.get(query.start), self.ids.get(query.end))
+        else {
+            return Ok(None);
+        };
+        if forbidden[start] {
+            return Ok(None);
+        }
+
+        let order = self.topological_order(&[start], end, &forbidden)?;
+        let mut position = vec![usize::MAX; self.names.len()];
+        for (i, &device) in order.iter().enumerate() {
+            position[device] = i;
+        }
+
+        let mut waypoints = Vec::with_capacity(query.required.len());
+        for device in &query.required {
+            match self.ids.get(device) {
+                Some(&id) if position[id] != usize::MAX => waypoints.push(id),
+                _ => return Ok(None),
+            }
+        }
+        if orderings
+            .iter()
+            .any(|&(before, after)| position[waypoints[before]] > position[waypoints[after]])
+        {
+            return Ok(None);
+        }
+        // A device listed twice is still a single requirement
+        waypoints.sort_unstable_by_key(|&device| position[device]);
+        waypoints.dedup();
+
+        let mut waypoint_index = vec![usize::MAX; self.names.len()];
+        for (i, &device) in waypoints.iter().enumerate() {
+            waypoint_index[device] = i;
+        }
+
+        Ok(Some(Staging {
+            start,
+            end,
+            order,
+            forbidden,
+            waypoints: waypoints.len(),
+            waypoint_index,
+        }))
+    }
+
+    fn forbidden(&self, query: &PathQuery) -> Vec<bool> {
+        let mut forbidden = vec![false; self.names.len()];
+        for device in &query.forbidden {
+            if let Some(&id) = self.ids.get(device) {
+                forbidden[id] = true;
+            }
+        }
+        forbidden
+    }
+
+    /**
+     * Connections of `device` avoiding the forbidden devices, none past `end`
+     */
+    fn children<'r>(
+        &'r self,
+        device: usize,
+        end: usize,
+        forbidden: &'r [bool],
+    ) -> impl Iterator<Item = usize> + 'r {
+        let connections = if device == end {
+            &[][..]
+        } else {
+            self.connections[device].as_slice()
+        };
+        connections
+            .iter()
+            .copied()
+            .filter(|&child| !forbidden[child])
+    }
+
+    /**
+     * Graphviz graph of every device labelled with its number of paths to the end and from the
+     * start, start and end in blue, required devices in gold, forbidden ones in grey, and the
+     * connections on at least one path matching the query in red
+     * Only devices reachable from the start or leading to the end are counted, any other
+     * device is on no path and left at 0 even when it sits on a cycle
+     */
+    fn to_dot(&self, query: &PathQuery, mode: CountMode) -> Result<String, RackError> {
+        let forbidden = self.forbidden(query);
+        let start = self.ids.get(query.start).copied();
+        let end = self.ids.get(query.end).copied().unwrap_or(usize::MAX);
+
+        let mut edges = vec![Vec::new(); self.names.len()];
+        let mut reversed_edges = vec![Vec::new(); self.names.len()];
+        for device in (0..self.names.len()).filter(|&device| !forbidden[device]) {
+            for child in self.children(device, end, &forbidden) {
+                edges[device].push(child);
+                reversed_edges[child].push(device);
+            }
+        }
+        let reached = Self::reachable(start.filter(|&start| !forbidden[start]), &edges);
+        let reaching = Self::reachable(
+            Some(end).filter(|&end| end < self.names.len() && !forbidden[end]),
+            &reversed_edges,
+        );
+        let outside: Vec<bool> = (0..self.names.len())
+            .map(|device| !reached[device] && !reaching[device])
+            .collect();
+        let roots: Vec<usize> = (0..self.names.len())
+            .filter(|&device| !outside[device])
+            .collect();
+        let order = self.topological_order(&roots, end, &outside)?;
+
+        let mut to_end = vec![0; self.names.len()];
+        for &device in order.iter().rev() {
+            to_end[device] = if device == end {
+                mode.one()
+            } else {
+                self.children(device, end, &outside)
+                    .try_fold(0, |sum, child| mode.add(sum, to_end[child]))?
+            };
+        }
+        let mut from_start = vec![0; self.names.len()];
+        if let Some(start) = start.filter(|&start| !forbidden[start]) {
+            from_start[start] = mode.one();
+        }
+        for &device in &order {
+            for child in self.children(device, end, &outside) {
+                from_start[child] = mode.add(from_start[child], from_start[device])?;
+            }
+        }
+
+        let on_path = self.connections_on_path(query)?;
+
+        let mut dot = String::from("digraph rack {\n");
+        for (device, &name) in self.names.iter().enumerate() {
+            let fill = if Some(device) == start || device == end {
+                ", style=filled, fillcolor=lightblue"
+            } else if query.required.contains(&name) {
+                ", style=filled, fillcolor=gold"
+            } else if forbidden[device] {
+                ", style=filled, fillcolor=grey"
+            } else {
+                ""
+            };
+            let _ = writeln!(
+                dot,
+                "    \"{name}\" [label=\"{name}\\nto {}: {}\\nfrom {}: {}\"{fill}];",
+                query.end, to_end[device], query.start, from_start[device]
+            );
+        }
+        for (device, connections) in self.connections.iter().enumerate() {
+            for &child in connections {
+                let colour = if on_path.contains(&(device, child)) {
+                    " [color=red, penwidth=2]"
+                } else {
+                    ""
+                };
+                let _ = writeln!(
+                    dot,
+                    "    \"{}\" -> \"{}\"{colour};",
+                    self.names[device], self.names[child]
+                );
+            }
+        }
+        dot.push_str("}\n");
+
+        Ok(dot)
+    }
+
+    /**
+     * Devices reachable from `root` following `edges`
+     */
+    fn reachable(root: Option<usize>, edges: &[Vec<usize>]) -> Vec<bool> {
+        let mut reachable = vec![false; edges.len()];
+        let mut stack = Vec::from_iter(root);
+        while let Some(device) = stack.pop() {
+            if !reachable[device] {
+                reachable[device] = true;
+                stack.extend(&edges[device]);
+            }
+        }
+        reachable
+    }
+
+    /**
+     * Connections between two stages, the first reached from the start and the second leading to
+     * the end with every required device visited
+     */
+    fn connections_on_path(&self, query: &PathQuery) -> Result<HashSet<(usize, usize)>, RackError> {
+        let mut on_path = HashSet::new();
+        let Some(staging) = self.staging(query)? else {
+            return Ok(on_path);
+        };
+
+        // Stages reached at each device, then those among them that go on to complete a path
+        let mut reached: Vec<Vec<usize>> = vec![Vec::new(); self.names.len()];
+        if let Some(stage) = staging.enter(staging.start, 0) {
+            reached[staging.start].push(stage);
+        }
+        for &device in &staging.order {
+            for stage in reached[device].clone() {
+                for child in self.children(device, staging.end, &staging.forbidden) {
+                    if let Some(next_stage) = staging.enter(child, stage)
+                        && !reached[child].contains(&next_stage)
+                    {
+                        reached[child].push(next_stage);
+                    }
+                }
+            }
+        }
+
+        let mut completes: Vec<Vec<usize>> = vec![Vec::new(); self.names.len()];
+        completes[staging.end].push(staging.waypoints);
+        for &device in staging.order.iter().rev() {
+            for &stage in &reached[device] {
+                for child in self.children(device, staging.end, &staging.forbidden) {
+                    if let Some(next_stage) = staging.enter(child, stage)
+                        && completes[child].contains(&next_stage)
+                    {
+                        if !completes[device].contains(&stage) {
+                            completes[device].push(stage);
+                        }
+                        on_path.insert((device, child));
+                    }
+                }
+            }
+        }
+
+        Ok(on_path)
+    }
+
+    /**
+     * Kahn's algorithm on the devices reachable from `start` without going through a forbidden
+     * device or past `end`, failing with one of the cycles when some devices are left over
+     */
+    fn topological_order(
+        &self,
+        roots: &[usize],
+        end: usize,
+        forbidden: &[bool],
+    ) -> Result<Vec<usize>, RackError> {
+        let children = |device: usize| self.children(device, end, forbidden);
+
+        let mut reachable = vec![false; self.names.len()];
+        for &root in roots {
+            reachable[root] = true;
+        }
+        let mut stack = roots.to_vec();
+        let mut in_degrees = vec![0_usize; self.names.len()];
+        while let Some(device) = stack.pop() {
+            for child in children(device) {
+                in_degrees[child] += 1;
+                if !reachable[child] {
+                    reachable[child] = true;
+                    stack.push(child);
+                }
+            }
+        }
+
+        let mut order = Vec::new();
+        let mut queue: VecDeque<usize> = roots
+            .iter()
+            .copied()
+            .filter(|&root| in_degrees[root] == 0)
+            .collect();
+        while let Some(device) = queue.pop_front() {
+            order.push(device);
+            for child in children(device) {
+                in_degrees[child] -= 1;
+                if in_degrees[child] == 0 {
+                    queue.push_back(child);
+                }
+            }
+        }
+
+        let left_over = (0..self.names.len()).find(|&device| in_degrees[device] > 0);
+        match left_over {
+            None => Ok(order),
+            Some(device) => Err(RackError::Cycle(self.cycle_before(
+                device,
+                &in_degrees,
+                &children,
+            ))),
+        }
+    }
+
+    /**
+     * Devices left over by Kahn's algorithm all have a left over parent, walking back through
+     * them from `device` ends up looping
+     */
+    fn cycle_before<I: Iterator<Item = usize>>(
+        &self,
+        device: usize,
+        in_degrees: &[usize],
+        children: &impl Fn(usize) -> I,
+    ) -> Vec<String> {
+        let mut parents = vec![usize::MAX; self.names.len()];
+        for parent in (0..self.names.len()).filter(|&parent| in_degrees[parent] > 0) {
+            for child in children(parent) {
+                if in_degrees[child] > 0 {
+                    parents[child] = parent;
+                }
             }
         }
 
-        visited.insert(state, paths);
-        paths
+        let mut seen = vec![false; self.names.len()];
+        let mut current = device;
+        while !seen[current] {
+            seen[current] = true;
+            current = parents[current];
+        }
+
+        let mut cycle = vec![current];
+        let mut parent = parents[current];
+        while parent != current {
+            cycle.push(parent);
+            parent = parents[parent];
+        }
+        cycle.push(current);
+        cycle
+            .into_iter()
+            .rev()
+            .map(|id| self.names[id].to_string())
+            .collect()
+    }
+}
+
+/**
+ * Query on the interned rack, a device entered at stage `i` moves to `i + 1` when it is the
+ * `i`th required device in topological order
+ */
+struct Staging {
+    start: usize,
+    end: usize,
+    order: Vec<usize>,
+    forbidden: Vec<bool>,
+    waypoints: usize,
+    waypoint_index: Vec<usize>,
+}
+
+impl Staging {
+    /**
+     * Stage after entering a device, None when it skipped an earlier required device
+     */
+    fn enter(&self, device: usize, stage: usize) -> Option<usize> {
+        match self.waypoint_index[device] {
+            usize::MAX => Some(stage),
+            i if i == stage => Some(stage + 1),
+            _ => None,
+        }
+    }
+}
+
+struct Diagnostics<'a> {
+    start: &'a str,
+    end: &'a str,
+    cycles: Vec<Vec<&'a str>>,
+    /** Devices that can't be reached from the start */
+    unreachable: Vec<&'a str>,
+    /** Devices that can't reach the end */
+    dead_ends: Vec<&'a str>,
+    duplicates: Vec<DuplicateDefinition<'a>>,
+}
+
+impl fmt::Display for Diagnostics<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for cycle in &self.cycles {
+            writeln!(f, "Cycle: {}", cycle.join(" -> "))?;
+        }
+        if !self.unreachable.is_empty() {
+            writeln!(
+                f,
+                "Unreachable from {}: {}",
+                self.start,
+                self.unreachable.join(", ")
+            )?;
+        }
+        if !self.dead_ends.is_empty() {
+            writeln!(f, "Can't reach {}: {}", self.end, self.dead_ends.join(", "))?;
+        }
+        for duplicate in &self.duplicates {
+            writeln!(
+                f,
+                "Duplicate definition of {} at line {}, first at line {}",
+                duplicate.device, duplicate.line, duplicate.first_line
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct Paths<'r, 'a> {
+    rack: &'r ServerRack<'a>,
+    end: &'a str,
+    /** Devices of the current path, each with the index of its next connection to explore */
+    stack: Vec<(&'a str, usize)>,
+}
+
+impl<'a> Iterator for Paths<'_, 'a> {
+    type Item = Vec<&'a str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (device, next_connection) = self.stack.last_mut()?;
+            let Some(&child) = self.rack.connections(device).get(*next_connection) else {
+                self.stack.pop();
+                continue;
+            };
+            *next_connection += 1;
+
+            if self.stack.iter().any(|&(device, _)| device == child) {
+                continue;
+            }
+            if child == self.end {
+                let mut path: Vec<&str> = self.stack.iter().map(|&(device, _)| device).collect();
+                path.push(child);
+                return Some(path);
+            }
+            self.stack.push((child, 0));
+        }
     }
 }
 
@@ -120,7 +997,7 @@ mod test {
 
         let result = p1(&input);
 
-        assert_eq!(result, 5);
+        assert_eq!(result, Ok(5));
     }
 
     #[test]
@@ -143,6 +1020,256 @@ mod test {
 
         let result = p2(&input);
 
-        assert_eq!(result, 2);
+        assert_eq!(result, Ok(2));
+    }
+
+    #[test]
+    fn paths_enumeration() {
+        let input = vec![
+            "aaa: you hhh".to_string(),
+            "you: bbb ccc".to_string(),
+            "bbb: ddd eee".to_string(),
+            "ccc: ddd eee fff".to_string(),
+            "ddd: ggg".to_string(),
+            "eee: out".to_string(),
+            "fff: out".to_string(),
+            "ggg: out".to_string(),
+            "hhh: ccc fff iii".to_string(),
+            "iii: out".to_string(),
+        ];
+        let rack = ServerRack::try_from(input.as_slice()).unwrap();
+
+        assert_eq!(
+            rack.paths_from(YOU, END).take(2).collect::<Vec<_>>(),
+            vec![
+                vec!["you", "bbb", "ddd", "ggg", "out"],
+                vec!["you", "bbb", "eee", "out"],
+            ]
+        );
+        assert_eq!(rack.paths_from(YOU, END).count(), 5);
+        assert_eq!(
+            rack.k_shortest_paths(YOU, END, 4),
+            vec![
+                vec!["you", "bbb", "eee", "out"],
+                vec!["you", "ccc", "eee", "out"],
+                vec!["you", "ccc", "fff", "out"],
+                vec!["you", "bbb", "ddd", "ggg", "out"],
+            ]
+        );
+        assert_eq!(rack.k_shortest_paths(YOU, END, 10).len(), 5);
+    }
+
+    #[test]
+    fn paths_with_constraints() {
+        let input = vec![
+            "aaa: you hhh".to_string(),
+            "you: bbb ccc".to_string(),
+            "bbb: ddd eee".to_string(),
+            "ccc: ddd eee fff".to_string(),
+            "ddd: ggg".to_string(),
+            "eee: out".to_string(),
+            "fff: out".to_string(),
+            "ggg: out".to_string(),
+            "hhh: ccc fff iii".to_string(),
+            "iii: out".to_string(),
+        ];
+        let rack = ServerRack::try_from(input.as_slice()).unwrap();
+        let query = |required: Vec<&'static str>, forbidden, orderings| PathQuery {
+            required,
+            forbidden,
+            orderings,
+            ..PathQuery::new(YOU, END)
+        };
+
+        assert_eq!(
+            rack.count_paths(&query(vec!["ddd"], vec![], vec![]), CountMode::Checked),
+            Ok(2)
+        );
+        assert_eq!(
+            rack.count_paths(
+                &query(vec!["ddd", "ccc"], vec![], vec![]),
+                CountMode::Checked
+            ),
+            Ok(1)
+        );
+        assert_eq!(
+            rack.count_paths(
+                &query(vec!["ddd", "ccc", "ddd"], vec![], vec![("ccc", "ddd")]),
+                CountMode::Checked
+            ),
+            Ok(1)
+        );
+        assert_eq!(
+            rack.count_paths(&query(vec![], vec!["eee"], vec![]), CountMode::Checked),
+            Ok(3)
+        );
+        assert_eq!(
+            rack.count_paths(
+                &query(vec!["ccc", "ddd"], vec![], vec![("ccc", "ddd")]),
+                CountMode::Checked
+            ),
+            Ok(1)
+        );
+        assert_eq!(
+            rack.count_paths(
+                &query(vec!["ccc", "ddd"], vec![], vec![("ddd", "ccc")]),
+                CountMode::Checked
+            ),
+            Ok(0)
+        );
+        assert_eq!(
+            rack.count_paths(
+                &query(vec!["ccc"], vec![], vec![("ddd", "ccc")]),
+                CountMode::Checked
+            ),
+            Err(RackError::UnknownWaypoint("ddd".to_string()))
+        );
+        assert_eq!(
+            rack.count_paths(
+                &query(vec!["ccc"], vec![], vec![("ccc", "ccc")]),
+                CountMode::Checked
+            ),
+            Err(RackError::InvalidOrdering("ccc:ccc".to_string()))
+        );
+        assert_eq!(
+            rack.count_paths(&PathQuery::new("aaa", "ccc"), CountMode::Checked),
+            Ok(2)
+        );
+    }
+
+    #[test]
+    fn counting_modes() {
+        let chain: Vec<String> = (0..2_000)
+            .map(|i| format!("d{i}: d{}", i + 1))
+            .chain(["d2000: out".to_string()])
+            .collect();
+        let rack = ServerRack::try_from(chain.as_slice()).unwrap();
+        let required: Vec<String> = (0..100).rev().map(|i| format!("d{}", i * 19)).collect();
+        let query = PathQuery {
+            required: required.iter().map(String::as_str).collect(),
+            orderings: vec![("d0", "d19")],
+            ..PathQuery::new("d0", END)
+        };
+        assert_eq!(rack.count_paths(&query, CountMode::Checked), Ok(1));
+
+        let ladder: Vec<String> = (0..70)
+            .flat_map(|i| {
+                [
+                    format!("l{i}: a{i} b{i}"),
+                    format!("a{i}: l{}", i + 1),
+                    format!("b{i}: l{}", i + 1),
+                ]
+            })
+            .chain(["l70: out".to_string()])
+            .collect();
+        let rack = ServerRack::try_from(ladder.as_slice()).unwrap();
+        let query = PathQuery::new("l0", END);
+        assert_eq!(
+            rack.count_paths(&query, CountMode::Checked),
+            Err(RackError::CountOverflow)
+        );
+        assert_eq!(rack.count_paths(&query, CountMode::Wide), Ok(1 << 70));
+        assert_eq!(
+            rack.count_paths(
+                &query,
+                CountMode::Modulo(NonZeroU64::new(1_000_000_007).unwrap())
+            ),
+            Ok((1 << 70) % 1_000_000_007)
+        );
+    }
+
+    #[test]
+    fn dot_export() {
+        let input = vec![
+            "svr: aaa bbb".to_string(),
+            "aaa: fft".to_string(),
+            "fft: ccc".to_string(),
+            "bbb: tty".to_string(),
+            "tty: ccc".to_string(),
+            "ccc: ddd eee".to_string(),
+            "ddd: hub".to_string(),
+            "hub: fff".to_string(),
+            "eee: dac".to_string(),
+            "dac: fff".to_string(),
+            "fff: ggg hhh".to_string(),
+            "ggg: out".to_string(),
+            "hhh: out".to_string(),
+        ];
+        let rack = ServerRack::try_from(input.as_slice()).unwrap();
+        let query = PathQuery {
+            required: vec![DAC, FFT],
+            ..PathQuery::new(SVR, END)
+        };
+
+        let dot = rack.to_dot(&query, CountMode::Checked).unwrap();
+
+        assert!(dot.starts_with("digraph rack {\n"));
+        assert!(dot.contains(
+            "    \"svr\" [label=\"svr\\nto out: 8\\nfrom svr: 1\", style=filled, fillcolor=lightblue];\n"
+        ));
+        assert!(dot.contains("    \"fff\" [label=\"fff\\nto out: 2\\nfrom svr: 4\"];\n"));
+        assert!(dot.contains(
+            "    \"dac\" [label=\"dac\\nto out: 2\\nfrom svr: 2\", style=filled, fillcolor=gold];\n"
+        ));
+        assert!(dot.contains("    \"svr\" -> \"aaa\" [color=red, penwidth=2];\n"));
+        assert!(dot.contains("    \"svr\" -> \"bbb\";\n"));
+        assert_eq!(dot.matches("color=red").count(), 10);
+    }
+
+    #[test]
+    fn dot_export_ignores_unrelated_cycles() {
+        let input = vec![
+            "you: out".to_string(),
+            "a: b".to_string(),
+            "b: a".to_string(),
+        ];
+        let rack = ServerRack::try_from(input.as_slice()).unwrap();
+        let query = PathQuery::new(YOU, END);
+
+        assert_eq!(rack.count_paths(&query, CountMode::Checked), Ok(1));
+        let dot = rack.to_dot(&query, CountMode::Checked).unwrap();
+        assert!(dot.contains("    \"a\" [label=\"a\\nto out: 0\\nfrom you: 0\"];\n"));
+        assert!(dot.contains("    \"you\" -> \"out\" [color=red, penwidth=2];\n"));
+    }
+
+    #[test]
+    fn diagnostics() {
+        let input = vec![
+            "you: aaa bbb".to_string(),
+            "aaa: ccc out".to_string(),
+            "ccc: aaa".to_string(),
+            "bbb: out".to_string(),
+            "zzz: bbb".to_string(),
+            "bbb: ddd".to_string(),
+        ];
+        let rack = ServerRack::try_from(input.as_slice()).unwrap();
+        let diagnostics = rack.diagnose(YOU, END);
+
+        assert_eq!(diagnostics.cycles, vec![vec!["aaa", "ccc", "aaa"]]);
+        assert_eq!(diagnostics.unreachable, vec!["zzz"]);
+        assert_eq!(diagnostics.dead_ends, vec!["bbb", "ddd", "zzz"]);
+        assert_eq!(
+            diagnostics.duplicates,
+            vec![DuplicateDefinition {
+                device: "bbb",
+                first_line: 4,
+                line: 6
+            }]
+        );
+        assert_eq!(
+            p1(&input),
+            Err(RackError::Cycle(vec![
+                "aaa".to_string(),
+                "ccc".to_string(),
+                "aaa".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn p1_missing_separator() {
+        let input = vec!["you: out".to_string(), "aaa out".to_string()];
+
+        assert_eq!(p1(&input), Err(RackError::MissingSeparator { line: 2 }));
     }
 }
@@ -1,16 +1,46 @@
 use std::{
+    env,
+    error::Error,
+    fmt::{self, Write},
     fs::File,
     io::{self, BufRead, BufReader},
 };
 
+use num_bigint::BigInt;
+
 fn main() -> io::Result<()> {
     let input = read_input()?;
 
-    let p1_result = p1(&input);
+    let args: Vec<String> = env::args().collect();
+    let big = args.iter().any(|arg| arg == "--big");
+    let explain_mode = args.iter().any(|arg| arg == "--explain");
+
+    if explain_mode {
+        for reading in [Reading::Horizontal, Reading::Vertical] {
+            let explanation = if big {
+                explain::<BigInt>(&input, reading)
+            } else {
+                explain::<i128>(&input, reading)
+            };
+            print!("{}", explanation.map_err(invalid_data)?);
+        }
+    }
+
+    let p1_result = if big {
+        grand_total::<BigInt>(&input, Reading::Horizontal).map(|n| n.to_string())
+    } else {
+        p1(&input).map(|n| n.to_string())
+    }
+    .map_err(invalid_data)?;
 
     println!("Part 1 result: {p1_result}");
 
-    let p2_result = p2(&input);
+    let p2_result = if big {
+        grand_total::<BigInt>(&input, Reading::Vertical).map(|n| n.to_string())
+    } else {
+        p2(&input).map(|n| n.to_string())
+    }
+    .map_err(invalid_data)?;
 
     println!("Part 2 result: {p2_result}");
 
@@ -22,100 +52,569 @@ fn read_input() -> io::Result<Vec<String>> {
     BufReader::new(file).lines().collect::<Result<_, _>>()
 }
 
-fn p1(input: &[String]) -> u64 {
-    Problems::from_horizontal_numbers(input).solve()
+fn invalid_data(error: WorksheetError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn p1(input: &[String]) -> Result<i128, WorksheetError> {
+    grand_total(input, Reading::Horizontal)
+}
+
+fn p2(input: &[String]) -> Result<i128, WorksheetError> {
+    grand_total(input, Reading::Vertical)
+}
+
+fn grand_total<N: Arithmetic>(input: &[String], reading: Reading) -> Result<N, WorksheetError> {
+    let worksheet = Worksheet::try_from(input)?;
+    Problems::read(&worksheet, reading)?.solve()
+}
+
+/**
+ * Lists every problem in reading order, its column block next to the extracted numbers
+ */
+fn explain<N: Arithmetic>(input: &[String], reading: Reading) -> Result<String, WorksheetError> {
+    let worksheet = Worksheet::try_from(input)?;
+    let problems = Problems::<N>::read(&worksheet, reading)?;
+    let mut explanation = String::new();
+
+    for (index, ((block, numbers), result)) in worksheet
+        .blocks_in(reading)
+        .into_iter()
+        .zip(problems.numbers.iter())
+        .zip(problems.results())
+        .enumerate()
+    {
+        let _ = writeln!(
+            explanation,
+            "Problem {}: columns {}-{}, {reading}",
+            index + 1,
+            block.start + 1,
+            block.end
+        );
+
+        let drawing = worksheet.render_block(block);
+        let width = block.end - block.start;
+        for line in 0..drawing.len().max(numbers.len()) {
+            let cells = drawing.get(line).map_or("", String::as_str);
+            let number = numbers
+                .get(line)
+                .map(ToString::to_string)
+                .unwrap_or_default();
+            let line = format!("  {cells:<width$}  {number}");
+            let _ = writeln!(explanation, "{}", line.trim_end());
+        }
+
+        let expression = numbers
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(&format!(" {} ", block.operation));
+        let _ = match result {
+            Ok(result) => writeln!(explanation, "  {expression} = {result}"),
+            Err(error) => writeln!(explanation, "  {expression} = {error}"),
+        };
+    }
+
+    let _ = match problems.solve() {
+        Ok(total) => writeln!(explanation, "Grand total: {total}"),
+        Err(error) => writeln!(explanation, "Grand total: {error}"),
+    };
+
+    Ok(explanation)
+}
+
+#[derive(Clone, Copy)]
+enum Reading {
+    Horizontal,
+    Vertical,
+}
+
+impl fmt::Display for Reading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reading::Horizontal => write!(f, "read top-to-bottom by rows"),
+            Reading::Vertical => write!(f, "read right-to-left by columns"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum WorksheetError {
+    Empty,
+    InvalidCharacter {
+        line: usize,
+        column: usize,
+        c: char,
+    },
+    MisalignedOperator {
+        column: usize,
+    },
+    MissingOperator {
+        column: usize,
+    },
+    UnknownOperation {
+        column: usize,
+        op: String,
+    },
+    SplitNumber {
+        line: usize,
+        column: usize,
+    },
+    NumberTooLarge {
+        line: usize,
+        column: usize,
+    },
+    Unsolvable {
+        problem: usize,
+        error: ArithmeticError,
+    },
+    TotalOverflow,
+}
+
+impl fmt::Display for WorksheetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorksheetError::Empty => write!(f, "worksheet needs number rows and an operator row"),
+            WorksheetError::InvalidCharacter { line, column, c } => {
+                write!(f, "invalid character {c:?} at line {line}, column {column}")
+            }
+            WorksheetError::MisalignedOperator { column } => {
+                write!(
+                    f,
+                    "operator at column {column} is not aligned with a problem"
+                )
+            }
+            WorksheetError::MissingOperator { column } => {
+                write!(f, "problem starting at column {column} has no operator")
+            }
+            WorksheetError::UnknownOperation { column, op } => {
+                write!(f, "unknown operation {op:?} at column {column}")
+            }
+            WorksheetError::SplitNumber { line, column } => {
+                write!(
+                    f,
+                    "several numbers on line {line} in problem starting at column {column}"
+                )
+            }
+            WorksheetError::NumberTooLarge { line, column } => {
+                write!(f, "number too large at line {line}, column {column}")
+            }
+            WorksheetError::Unsolvable { problem, error } => {
+                write!(f, "problem {problem} cannot be solved: {error}")
+            }
+            WorksheetError::TotalOverflow => write!(f, "grand total overflows"),
+        }
+    }
+}
+
+impl Error for WorksheetError {}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ArithmeticError {
+    Overflow,
+    DivisionByZero,
+    NegativeExponent,
+}
+
+impl fmt::Display for ArithmeticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArithmeticError::Overflow => write!(f, "overflow"),
+            ArithmeticError::DivisionByZero => write!(f, "division by zero"),
+            ArithmeticError::NegativeExponent => write!(f, "negative exponent"),
+        }
+    }
+}
+
+/**
+ * Checked arithmetic shared by the fixed-width and the big-integer modes
+ */
+trait Arithmetic: Sized + Clone + Ord + fmt::Display {
+    fn zero() -> Self;
+    fn from_digits(digits: &[char]) -> Option<Self>;
+    fn add(&self, other: &Self) -> Result<Self, ArithmeticError>;
+    fn sub(&self, other: &Self) -> Result<Self, ArithmeticError>;
+    fn mul(&self, other: &Self) -> Result<Self, ArithmeticError>;
+    fn div(&self, other: &Self) -> Result<Self, ArithmeticError>;
+    fn pow(&self, exponent: &Self) -> Result<Self, ArithmeticError>;
+}
+
+impl Arithmetic for i128 {
+    fn zero() -> Self {
+        0
+    }
+
+    fn from_digits(digits: &[char]) -> Option<Self> {
+        digits.iter().try_fold(0i128, |acc, c| {
+            acc.checked_mul(10)?
+                .checked_add(i128::from(c.to_digit(10)?))
+        })
+    }
+
+    fn add(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        self.checked_add(*other).ok_or(ArithmeticError::Overflow)
+    }
+
+    fn sub(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        self.checked_sub(*other).ok_or(ArithmeticError::Overflow)
+    }
+
+    fn mul(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        self.checked_mul(*other).ok_or(ArithmeticError::Overflow)
+    }
+
+    fn div(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        if *other == 0 {
+            return Err(ArithmeticError::DivisionByZero);
+        }
+        self.checked_div(*other).ok_or(ArithmeticError::Overflow)
+    }
+
+    fn pow(&self, exponent: &Self) -> Result<Self, ArithmeticError> {
+        if *exponent < 0 {
+            return Err(ArithmeticError::NegativeExponent);
+        }
+        let exponent = u32::try_from(*exponent).map_err(|_| ArithmeticError::Overflow)?;
+        self.checked_pow(exponent).ok_or(ArithmeticError::Overflow)
+    }
 }
 
-fn p2(input: &[String]) -> u64 {
-    Problems::from_vertical_numbers(input).solve()
+impl Arithmetic for BigInt {
+    fn zero() -> Self {
+        BigInt::ZERO
+    }
+
+    fn from_digits(digits: &[char]) -> Option<Self> {
+        let digits: String = digits.iter().collect();
+        BigInt::parse_bytes(digits.as_bytes(), 10)
+    }
+
+    fn add(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        Ok(self + other)
+    }
+
+    fn sub(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        Ok(self - other)
+    }
+
+    fn mul(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        Ok(self * other)
+    }
+
+    fn div(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        if *other == BigInt::ZERO {
+            return Err(ArithmeticError::DivisionByZero);
+        }
+        Ok(self / other)
+    }
+
+    fn pow(&self, exponent: &Self) -> Result<Self, ArithmeticError> {
+        if *exponent < BigInt::ZERO {
+            return Err(ArithmeticError::NegativeExponent);
+        }
+        let exponent = u32::try_from(exponent).map_err(|_| ArithmeticError::Overflow)?;
+        Ok(BigInt::pow(self, exponent))
+    }
 }
 
+#[derive(Clone, Copy)]
 enum Operation {
     Add,
+    Subtract,
     Multiply,
+    Divide,
+    Max,
+    Min,
+    Power,
 }
 
-struct Problems {
-    numbers: Vec<Vec<u64>>,
-    operations: Vec<Operation>,
+impl Operation {
+    fn parse(op: &str, column: usize) -> Result<Self, WorksheetError> {
+        match op {
+            "+" => Ok(Operation::Add),
+            "-" => Ok(Operation::Subtract),
+            "*" => Ok(Operation::Multiply),
+            "/" => Ok(Operation::Divide),
+            "max" => Ok(Operation::Max),
+            "min" => Ok(Operation::Min),
+            "^" => Ok(Operation::Power),
+            _ => Err(WorksheetError::UnknownOperation {
+                column,
+                op: op.to_string(),
+            }),
+        }
+    }
+
+    /**
+     * Left fold in reading order, except `^` which is right-associative
+     * (`a ^ b ^ c` is `a ^ (b ^ c)`), an empty problem yields 0
+     */
+    fn apply<N: Arithmetic>(self, numbers: &[N]) -> Result<N, ArithmeticError> {
+        if let Operation::Power = self {
+            let Some((last, rest)) = numbers.split_last() else {
+                return Ok(N::zero());
+            };
+            return rest
+                .iter()
+                .rev()
+                .try_fold(last.clone(), |acc, n| self.combine(n, &acc));
+        }
+
+        let Some((first, rest)) = numbers.split_first() else {
+            return Ok(N::zero());
+        };
+        rest.iter()
+            .try_fold(first.clone(), |acc, n| self.combine(&acc, n))
+    }
+
+    fn combine<N: Arithmetic>(self, left: &N, right: &N) -> Result<N, ArithmeticError> {
+        match self {
+            Operation::Add => left.add(right),
+            Operation::Subtract => left.sub(right),
+            Operation::Multiply => left.mul(right),
+            Operation::Divide => left.div(right),
+            Operation::Max => Ok(left.max(right).clone()),
+            Operation::Min => Ok(left.min(right).clone()),
+            Operation::Power => left.pow(right),
+        }
+    }
 }
 
-impl Problems {
-    fn from_horizontal_numbers(input: &[String]) -> Self {
-        let num_cols = input.first().map_or(0, |l| l.split_whitespace().count());
-        let mut numbers: Vec<Vec<u64>> = vec![vec![]; num_cols];
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Add => write!(f, "+"),
+            Operation::Subtract => write!(f, "-"),
+            Operation::Multiply => write!(f, "*"),
+            Operation::Divide => write!(f, "/"),
+            Operation::Max => write!(f, "max"),
+            Operation::Min => write!(f, "min"),
+            Operation::Power => write!(f, "^"),
+        }
+    }
+}
 
-        for line in &input[..input.len() - 1] {
-            for (col, num) in line.split_whitespace().enumerate() {
-                numbers[col].push(num.parse::<u64>().unwrap());
+/**
+ * Columns `start..end` holding one problem, delimited by all-blank columns
+ */
+struct Block {
+    start: usize,
+    end: usize,
+    operation: Operation,
+}
+
+/**
+ * Number rows and operator row, padded with spaces to the same width
+ */
+struct Worksheet {
+    rows: Vec<Vec<char>>,
+    operators: Vec<char>,
+    blocks: Vec<Block>,
+}
+
+impl TryFrom<&[String]> for Worksheet {
+    type Error = WorksheetError;
+
+    fn try_from(input: &[String]) -> Result<Self, Self::Error> {
+        let Some((operator_line, number_lines)) = input.split_last() else {
+            return Err(WorksheetError::Empty);
+        };
+        if number_lines.is_empty() {
+            return Err(WorksheetError::Empty);
+        }
+
+        let width = input
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+        let pad = |line: &String| {
+            let mut chars: Vec<char> = line.chars().collect();
+            chars.resize(width, ' ');
+            chars
+        };
+
+        let rows: Vec<Vec<char>> = number_lines.iter().map(pad).collect();
+        for (y, row) in rows.iter().enumerate() {
+            if let Some(x) = row.iter().position(|c| !c.is_ascii_digit() && *c != ' ') {
+                return Err(WorksheetError::InvalidCharacter {
+                    line: y + 1,
+                    column: x + 1,
+                    c: row[x],
+                });
             }
         }
+        let operators = pad(operator_line);
 
-        let operations = input
-            .last()
-            .unwrap()
-            .split_whitespace()
-            .map(|op| match op {
-                "+" => Operation::Add,
-                "*" => Operation::Multiply,
-                _ => panic!("Unknown operation: {op}"),
-            })
-            .collect();
+        let blocks = Self::find_blocks(&rows, &operators, width)?;
 
-        Problems {
-            numbers,
-            operations,
+        Ok(Worksheet {
+            rows,
+            operators,
+            blocks,
+        })
+    }
+}
+
+impl Worksheet {
+    fn blocks_in(&self, reading: Reading) -> Vec<&Block> {
+        match reading {
+            Reading::Horizontal => self.blocks.iter().collect(),
+            Reading::Vertical => self.blocks.iter().rev().collect(),
         }
     }
 
-    fn from_vertical_numbers(input: &[String]) -> Self {
-        let num_cols = input.first().map_or(0, |l| l.split_whitespace().count());
-        let mut numbers: Vec<Vec<u64>> = vec![vec![]; num_cols];
-        let mut index = 0usize;
+    fn render_block(&self, block: &Block) -> Vec<String> {
+        self.rows
+            .iter()
+            .chain([&self.operators])
+            .map(|row| row[block.start..block.end].iter().collect())
+            .collect()
+    }
+
+    fn find_blocks(
+        rows: &[Vec<char>],
+        operators: &[char],
+        width: usize,
+    ) -> Result<Vec<Block>, WorksheetError> {
+        let is_blank = |x: usize| rows.iter().all(|row| row[x] == ' ');
 
-        for x in (0..input[0].len()).rev() {
-            let mut number = 0u64;
-            for line in &input[..input.len() - 1] {
-                let c = line.chars().nth(x).unwrap();
-                if c.is_whitespace() {
-                    continue;
+        let mut blocks = vec![];
+        let mut x = 0;
+        while x < width {
+            if is_blank(x) {
+                if operators[x] != ' ' {
+                    return Err(WorksheetError::MisalignedOperator { column: x + 1 });
                 }
-                number = number * 10 + c.to_string().parse::<u64>().unwrap();
+                x += 1;
+                continue;
+            }
+
+            let start = x;
+            while x < width && !is_blank(x) {
+                x += 1;
             }
-            if number == 0 {
-                index += 1;
+            blocks.push(Self::read_block(operators, start, x)?);
+        }
+
+        Ok(blocks)
+    }
+
+    /**
+     * The operator must sit in the first column of its problem, alone
+     */
+    fn read_block(operators: &[char], start: usize, end: usize) -> Result<Block, WorksheetError> {
+        let span = &operators[start..end];
+        if span[0] == ' ' {
+            return Err(span.iter().position(|c| *c != ' ').map_or(
+                WorksheetError::MissingOperator { column: start + 1 },
+                |x| WorksheetError::MisalignedOperator {
+                    column: start + x + 1,
+                },
+            ));
+        }
+
+        let len = span.iter().position(|c| *c == ' ').unwrap_or(span.len());
+        if let Some(x) = span[len..].iter().position(|c| *c != ' ') {
+            return Err(WorksheetError::MisalignedOperator {
+                column: start + len + x + 1,
+            });
+        }
+
+        let op: String = span[..len].iter().collect();
+        Ok(Block {
+            start,
+            end,
+            operation: Operation::parse(&op, start + 1)?,
+        })
+    }
+
+    fn read_horizontal<N: Arithmetic>(&self, block: &Block) -> Result<Vec<N>, WorksheetError> {
+        let mut numbers = vec![];
+        for (y, row) in self.rows.iter().enumerate() {
+            let cells = &row[block.start..block.end];
+            let Some(first) = cells.iter().position(|c| *c != ' ') else {
                 continue;
+            };
+            let last = cells.iter().rposition(|c| *c != ' ').unwrap();
+            if cells[first..=last].contains(&' ') {
+                return Err(WorksheetError::SplitNumber {
+                    line: y + 1,
+                    column: block.start + 1,
+                });
             }
-            numbers[index].push(number);
+            numbers.push(N::from_digits(&cells[first..=last]).ok_or(
+                WorksheetError::NumberTooLarge {
+                    line: y + 1,
+                    column: block.start + first + 1,
+                },
+            )?);
         }
+        Ok(numbers)
+    }
 
-        let operations = input
-            .last()
-            .unwrap()
-            .split_whitespace()
+    /**
+     * Right-to-left, one number per column, most significant digit at the top
+     */
+    fn read_vertical<N: Arithmetic>(&self, block: &Block) -> Result<Vec<N>, WorksheetError> {
+        (block.start..block.end)
             .rev()
-            .map(|op| match op {
-                "+" => Operation::Add,
-                "*" => Operation::Multiply,
-                _ => panic!("Unknown operation: {op}"),
+            .map(|x| {
+                let column = self.rows.iter().map(|row| row[x]);
+                let digits: Vec<char> = column.clone().filter(|c| *c != ' ').collect();
+                N::from_digits(&digits).ok_or(WorksheetError::NumberTooLarge {
+                    line: column.take_while(|c| *c == ' ').count() + 1,
+                    column: x + 1,
+                })
+            })
+            .collect()
+    }
+}
+
+struct Problems<N> {
+    numbers: Vec<Vec<N>>,
+    operations: Vec<Operation>,
+}
+
+impl<N: Arithmetic> Problems<N> {
+    fn read(worksheet: &Worksheet, reading: Reading) -> Result<Self, WorksheetError> {
+        let blocks = worksheet.blocks_in(reading);
+        let numbers = blocks
+            .iter()
+            .map(|block| match reading {
+                Reading::Horizontal => worksheet.read_horizontal(block),
+                Reading::Vertical => worksheet.read_vertical(block),
             })
-            .collect();
+            .collect::<Result<_, _>>()?;
+        let operations = blocks.iter().map(|block| block.operation).collect();
 
-        Problems {
+        Ok(Problems {
             numbers,
             operations,
-        }
+        })
     }
 
-    fn solve(&self) -> u64 {
+    fn results(&self) -> Vec<Result<N, ArithmeticError>> {
         self.numbers
             .iter()
             .zip(self.operations.iter())
-            .map(|(nums, op)| match op {
-                Operation::Add => nums.iter().sum::<u64>(),
-                Operation::Multiply => nums.iter().product::<u64>(),
+            .map(|(nums, op)| op.apply(nums))
+            .collect()
+    }
+
+    fn solve(&self) -> Result<N, WorksheetError> {
+        self.results()
+            .into_iter()
+            .enumerate()
+            .try_fold(N::zero(), |total, (problem, result)| {
+                let result = result.map_err(|error| WorksheetError::Unsolvable {
+                    problem: problem + 1,
+                    error,
+                })?;
+                total
+                    .add(&result)
+                    .map_err(|_| WorksheetError::TotalOverflow)
             })
-            .sum()
     }
 }
 
@@ -130,7 +629,7 @@ mod test {
             "4 5 6".to_string(),
             "+ * +".to_string(),
         ];
-        assert_eq!(p1(&input), 24); // (1 + 4) + (2 * 5) + (3 + 6)
+        assert_eq!(p1(&input), Ok(24)); // (1 + 4) + (2 * 5) + (3 + 6)
     }
 
     #[test]
@@ -141,7 +640,7 @@ mod test {
             "  6 98  215 314".to_string(),
             "*   +   *   +  ".to_string(),
         ];
-        assert_eq!(p1(&input), 4_277_556); // cf. reasoning in README.md
+        assert_eq!(p1(&input), Ok(4_277_556)); // cf. reasoning in README.md
     }
 
     #[test]
@@ -151,7 +650,7 @@ mod test {
             "67 8  9".to_string(),
             "+  *  +".to_string(),
         ];
-        assert_eq!(p2(&input), 254); // (5 + 9) + (4 * 38) + (27 + 12)
+        assert_eq!(p2(&input), Ok(254)); // (5 + 9) + (4 * 38) + (27 + 12)
     }
 
     #[test]
@@ -162,6 +661,147 @@ mod test {
             "  6 98  215 314".to_string(),
             "*   +   *   +  ".to_string(),
         ];
-        assert_eq!(p2(&input), 3_263_827); // cf. reasoning in README.md
+        assert_eq!(p2(&input), Ok(3_263_827)); // cf. reasoning in README.md
+    }
+
+    #[test]
+    fn p2_zero_only_column() {
+        let input = vec!["10 2".to_string(), "30 4".to_string(), "+  *".to_string()];
+        assert_eq!(p2(&input), Ok(37)); // (24) + (0 + 13)
+    }
+
+    #[test]
+    fn p1_ragged_lines() {
+        let input = vec!["12 3".to_string(), " 4".to_string(), "*  +".to_string()];
+        assert_eq!(p1(&input), Ok(51)); // (12 * 4) + 3
+    }
+
+    #[test]
+    fn misaligned_operator() {
+        let input = vec!["12 3".to_string(), "45 6".to_string(), " * +".to_string()];
+        assert_eq!(
+            p1(&input),
+            Err(WorksheetError::MisalignedOperator { column: 2 })
+        );
+    }
+
+    #[test]
+    fn operator_in_separator_column() {
+        let input = vec!["12 3".to_string(), "45 6".to_string(), "* ++".to_string()];
+        assert_eq!(
+            p2(&input),
+            Err(WorksheetError::MisalignedOperator { column: 3 })
+        );
+    }
+
+    #[test]
+    fn unknown_operation() {
+        let input = vec!["1 2".to_string(), "% +".to_string()];
+        assert_eq!(
+            p1(&input),
+            Err(WorksheetError::UnknownOperation {
+                column: 1,
+                op: "%".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn p1_more_operations() {
+        let input = vec![
+            "20 20 7   1   3".to_string(),
+            " 3  4   9  50 2".to_string(),
+            " 5    300   2 2".to_string(),
+            "-  /  max min ^".to_string(),
+        ];
+        assert_eq!(p1(&input), Ok(12 + 5 + 300 + 1 + 81)); // 3 ^ (2 ^ 2) = 81
+    }
+
+    #[test]
+    fn operation_combine() {
+        assert_eq!(Operation::Power.combine(&2_i128, &10), Ok(1024));
+        assert_eq!(Operation::Min.combine(&2_i128, &-3), Ok(-3));
+        assert_eq!(
+            Operation::Power.combine(&2_i128, &-1),
+            Err(ArithmeticError::NegativeExponent)
+        );
+    }
+
+    #[test]
+    fn p1_beyond_i64() {
+        let input = vec![
+            "18446744073709551615 1".to_string(),
+            "                   0 2".to_string(),
+            "+                    *".to_string(),
+        ];
+        assert_eq!(p1(&input), Ok(i128::from(u64::MAX) + 2));
+    }
+
+    #[test]
+    fn p1_checked_overflow() {
+        let input = vec![
+            "99999999999999999999 1".to_string(),
+            "99999999999999999999 2".to_string(),
+            "*                    +".to_string(),
+        ];
+        assert_eq!(
+            p1(&input),
+            Err(WorksheetError::Unsolvable {
+                problem: 1,
+                error: ArithmeticError::Overflow
+            })
+        );
+        assert_eq!(
+            grand_total::<BigInt>(&input, Reading::Horizontal).map(|n| n.to_string()),
+            Ok("9999999999999999999800000000000000000004".to_string())
+        );
+    }
+
+    #[test]
+    fn p1_division_by_zero() {
+        let input = vec!["12 1".to_string(), " 0 1".to_string(), "/  +".to_string()];
+        let worksheet = Worksheet::try_from(input.as_slice()).unwrap();
+        let problems = Problems::<i128>::read(&worksheet, Reading::Horizontal).unwrap();
+        assert_eq!(
+            problems.results(),
+            vec![Err(ArithmeticError::DivisionByZero), Ok(2)]
+        );
+    }
+
+    #[test]
+    fn p2_explain_example() {
+        let input = vec![
+            "123 328  51 64 ".to_string(),
+            " 45 64  387 23 ".to_string(),
+            "  6 98  215 314".to_string(),
+            "*   +   *   +  ".to_string(),
+        ];
+        let explanation = explain::<i128>(&input, Reading::Vertical).unwrap();
+        assert_eq!(
+            explanation.lines().take(6).collect::<Vec<_>>(),
+            vec![
+                "Problem 1: columns 13-15, read right-to-left by columns",
+                "  64   4",
+                "  23   431",
+                "  314  623",
+                "  +",
+                "  4 + 431 + 623 = 1058",
+            ]
+        );
+        assert!(explanation.contains("  356 * 24 * 1 = 8544\n"));
+        assert!(explanation.ends_with("Grand total: 3263827\n"));
+    }
+
+    #[test]
+    fn p1_explain_overflow() {
+        let input = vec![
+            "99999999999999999999 1".to_string(),
+            "99999999999999999999 2".to_string(),
+            "*                    +".to_string(),
+        ];
+        let explanation = explain::<i128>(&input, Reading::Horizontal).unwrap();
+        assert!(explanation.contains("  99999999999999999999 * 99999999999999999999 = overflow\n"));
+        assert!(explanation.contains("  1 + 2 = 3\n"));
+        assert!(explanation.ends_with("Grand total: problem 1 cannot be solved: overflow\n"));
     }
 }
@@ -1,121 +1,810 @@
 use std::{
-    fs::File,
+    env,
+    error::Error,
+    fmt::{self, Write},
+    fs::{self, File},
     io::{self, BufRead, BufReader},
+    iter,
+    str::FromStr,
 };
 
+use num_bigint::{BigInt, BigUint};
+use num_rational::BigRational;
+
 fn main() -> io::Result<()> {
     let input = read_input()?;
 
-    let p1_result = p1(&input);
+    let args: Vec<String> = env::args().collect();
+    let heatmap = args.iter().any(|arg| arg == "--heatmap");
+    let weights = option_value(&args, "--weights")
+        .map_or(Ok(Weights::default()), str::parse)
+        .map_err(invalid_data)?;
+    let mode = option_value(&args, "--mode");
 
-    println!("Part 1 result: {p1_result}");
+    if mode.is_some() || args.iter().any(|arg| arg == "--exits") {
+        print!(
+            "{}",
+            exit_report(&input, mode, weights).map_err(invalid_data)?
+        );
+    }
 
-    let p2_result = p2(&input);
+    print_part(1, p1(&input))?;
+    print_part(2, p2(&input))?;
 
-    println!("Part 2 result: {p2_result}");
+    let render = args.iter().any(|arg| arg == "--render");
+    let svg_path = option_value(&args, "--svg");
+    let ppm_path = option_value(&args, "--ppm");
+    if render || svg_path.is_some() || ppm_path.is_some() {
+        let manifold = TachyonManifold::run(&input, weights).map_err(invalid_data)?;
+        let rendering = Rendering::from(&input, &manifold, heatmap);
+
+        if render {
+            print!("{}", rendering.to_ansi());
+        }
+        if let Some(path) = svg_path {
+            fs::write(path, rendering.to_svg())?;
+        }
+        if let Some(path) = ppm_path {
+            fs::write(path, rendering.to_ppm())?;
+        }
+    }
 
     Ok(())
 }
 
+/**
+ * Parts count in u64, a diagram overflowing it skips them instead of stopping the other modes
+ */
+fn print_part(part: u8, result: Result<u64, DiagramError>) -> io::Result<()> {
+    match result {
+        Ok(result) => println!("Part {part} result: {result}"),
+        Err(DiagramError::Overflow { line }) => {
+            println!("Part {part} skipped: timeline count overflows u64 at line {line}");
+        }
+        Err(error) => return Err(invalid_data(error)),
+    }
+    Ok(())
+}
+
 fn read_input() -> io::Result<Vec<String>> {
     let file = File::open("input/raw.txt")?;
     BufReader::new(file).lines().collect::<Result<_, _>>()
 }
 
-fn p1(input: &[String]) -> u64 {
-    let mut tachyon_manifold = TachyonManifold::from(input.first().unwrap());
+fn invalid_data(error: DiagramError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn option_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
 
-    input[1..]
-        .iter()
-        .map(DiagramStep::from)
-        .for_each(|step| tachyon_manifold.step(&step));
+/**
+ * Splitters are counted whatever the number of timelines, so those are kept exact
+ */
+fn p1(input: &[String]) -> Result<u64, DiagramError> {
+    Ok(TachyonManifold::<BigUint>::run(input, Weights::default())?.splitters)
+}
 
-    tachyon_manifold.splitters
+fn p2(input: &[String]) -> Result<u64, DiagramError> {
+    let manifold = TachyonManifold::<u64>::run(input, Weights::default())?;
+    manifold
+        .total()
+        .ok_or(DiagramError::Overflow { line: input.len() })
 }
 
-fn p2(input: &[String]) -> u64 {
-    let mut tachyon_manifold = TachyonManifold::from(input.first().unwrap());
+/**
+ * Timeline count or exit probability reaching each column of the last row,
+ * and what left the diagram sideways on the way down
+ */
+struct ExitReport<F> {
+    columns: Vec<F>,
+    lost_left: F,
+    lost_right: F,
+    total: F,
+}
+
+impl<F: Flow> ExitReport<F> {
+    fn from(manifold: TachyonManifold<F>, line: usize) -> Result<Self, DiagramError> {
+        let total = manifold.total().ok_or(DiagramError::Overflow { line })?;
+
+        Ok(ExitReport {
+            columns: manifold.timelines,
+            lost_left: manifold.lost_left,
+            lost_right: manifold.lost_right,
+            total,
+        })
+    }
+}
+
+impl<F: Flow> fmt::Display for ExitReport<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (x, flow) in self
+            .columns
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| !c.is_zero())
+        {
+            writeln!(f, "Column {}: {flow}", x + 1)?;
+        }
+        writeln!(f, "Lost left: {}", self.lost_left)?;
+        writeln!(f, "Lost right: {}", self.lost_right)?;
+        writeln!(f, "Total: {}", self.total)
+    }
+}
+
+fn exits<F: Flow>(input: &[String], weights: Weights) -> Result<ExitReport<F>, DiagramError> {
+    ExitReport::from(TachyonManifold::<F>::run(input, weights)?, input.len())
+}
+
+/**
+ * Exit report in the flow picked by `--mode`, timeline counts in u64 by default
+ */
+fn exit_report(
+    input: &[String],
+    mode: Option<&str>,
+    weights: Weights,
+) -> Result<String, DiagramError> {
+    match mode {
+        Some("u128") => exits::<u128>(input, weights).map(|r| r.to_string()),
+        Some("big") => exits::<BigUint>(input, weights).map(|r| r.to_string()),
+        Some("f64") => exits::<f64>(input, weights).map(|r| r.to_string()),
+        Some("rational") => exits::<BigRational>(input, weights).map(|r| r.to_string()),
+        Some("u64") | None => exits::<u64>(input, weights).map(|r| r.to_string()),
+        Some(mode) => Err(DiagramError::UnknownMode(mode.to_string())),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum DiagramError {
+    Empty,
+    UnknownCell { line: usize, column: usize, c: char },
+    InvalidWeights,
+    UnknownMode(String),
+    Overflow { line: usize },
+}
 
-    input[1..]
-        .iter()
-        .map(DiagramStep::from)
-        .for_each(|step| tachyon_manifold.step(&step));
-    
-    tachyon_manifold.timelines.iter().sum::<u64>()
+impl fmt::Display for DiagramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiagramError::Empty => write!(f, "diagram has no source line"),
+            DiagramError::UnknownCell { line, column, c } => {
+                write!(f, "unknown cell {c:?} at line {line}, column {column}")
+            }
+            DiagramError::InvalidWeights => {
+                write!(
+                    f,
+                    "weights must be `left,straight,right` with left + right > 0 and a sum within u64"
+                )
+            }
+            DiagramError::UnknownMode(mode) => write!(
+                f,
+                "unknown mode {mode:?}, expected u64, u128, big, f64 or rational"
+            ),
+            DiagramError::Overflow { line } => write!(f, "timeline count overflows at line {line}"),
+        }
+    }
 }
 
+impl Error for DiagramError {}
+
+#[derive(Clone, Copy)]
 enum DiagramCell {
     Empty,
+    Source,
     Splitter,
+    Absorber,
+    LeftDeflector,
+    RightDeflector,
+    ThreeWaySplitter,
+    WeightedSplitter,
 }
 
-impl From<char> for DiagramCell {
-    fn from(value: char) -> Self {
+impl TryFrom<char> for DiagramCell {
+    type Error = char;
+
+    fn try_from(value: char) -> Result<Self, Self::Error> {
         match value {
-            '^' => DiagramCell::Splitter,
-            _ => DiagramCell::Empty,
+            '.' => Ok(DiagramCell::Empty),
+            'S' => Ok(DiagramCell::Source),
+            '^' => Ok(DiagramCell::Splitter),
+            '#' => Ok(DiagramCell::Absorber),
+            '/' => Ok(DiagramCell::LeftDeflector),
+            '\\' => Ok(DiagramCell::RightDeflector),
+            '*' => Ok(DiagramCell::ThreeWaySplitter),
+            '%' => Ok(DiagramCell::WeightedSplitter),
+            _ => Err(value),
         }
     }
 }
 
-struct DiagramStep {
-    cells: Vec<DiagramCell>,
+#[derive(Clone, Copy)]
+enum Transfer {
+    Duplicate,
+    Divide,
 }
 
-impl From<&String> for DiagramStep {
-    fn from(value: &String) -> Self {
-        let cells = value.chars().map(DiagramCell::from).collect();
+/**
+ * Where the beam reaching a cell goes on the next row, as column offsets
+ * With `Transfer::Divide` the flow is shared according to `Weights`
+ */
+struct Rule {
+    outputs: &'static [isize],
+    transfer: Transfer,
+    splits: bool,
+}
 
-        DiagramStep { cells }
+impl DiagramCell {
+    fn rule(self) -> Rule {
+        let (outputs, transfer, splits): (&'static [isize], _, _) = match self {
+            DiagramCell::Empty | DiagramCell::Source => (&[0], Transfer::Duplicate, false),
+            DiagramCell::Splitter => (&[-1, 1], Transfer::Duplicate, true),
+            DiagramCell::Absorber => (&[], Transfer::Duplicate, false),
+            DiagramCell::LeftDeflector => (&[-1], Transfer::Duplicate, false),
+            DiagramCell::RightDeflector => (&[1], Transfer::Duplicate, false),
+            DiagramCell::ThreeWaySplitter => (&[-1, 0, 1], Transfer::Duplicate, true),
+            DiagramCell::WeightedSplitter => (&[-1, 1], Transfer::Divide, true),
+        };
+
+        Rule {
+            outputs,
+            transfer,
+            splits,
+        }
     }
 }
 
-struct TachyonManifold {
-    timelines: Vec<u64>,
-    splitters: u64,
+#[derive(Clone, Copy)]
+struct Weights {
+    left: u64,
+    straight: u64,
+    right: u64,
 }
 
-impl From<&String> for TachyonManifold {
-    fn from(value: &String) -> Self {
-        let timelines = value
-            .chars()
-            .map(|c| match c {
-                'S' => 1,
-                _ => 0,
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            left: 1,
+            straight: 1,
+            right: 1,
+        }
+    }
+}
+
+impl FromStr for Weights {
+    type Err = DiagramError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let weights = s
+            .split(',')
+            .map(|w| w.trim().parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| DiagramError::InvalidWeights)?;
+        let &[left, straight, right] = weights.as_slice() else {
+            return Err(DiagramError::InvalidWeights);
+        };
+        // Splitters must send their flow somewhere, and flows divide by the sum of the weights
+        if left.checked_add(right).is_none_or(|sum| sum == 0)
+            || left
+                .checked_add(straight)
+                .and_then(|sum| sum.checked_add(right))
+                .is_none()
+        {
+            return Err(DiagramError::InvalidWeights);
+        }
+
+        Ok(Weights {
+            left,
+            straight,
+            right,
+        })
+    }
+}
+
+impl Weights {
+    fn of(self, offset: isize) -> u64 {
+        match offset {
+            ..0 => self.left,
+            0 => self.straight,
+            1.. => self.right,
+        }
+    }
+}
+
+/**
+ * What travels down the manifold: a timeline count, or a probability mass
+ * which every splitter divides instead of duplicating
+ */
+trait Flow: Clone + fmt::Display {
+    const CONSERVED: bool;
+
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn is_zero(&self) -> bool;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    /**
+     * Shares proportional to `weights`. Integer flows round every share down and
+     * hand what is left to the last share, so a single timeline reaching a `%`
+     * goes right and none goes left
+     * `weights` sum within u64, as checked when parsing `Weights`
+     */
+    fn divide(&self, weights: &[u64]) -> Option<Vec<Self>>;
+}
+
+macro_rules! integer_flow {
+    ($($t:ty),*) => {$(
+        impl Flow for $t {
+            const CONSERVED: bool = false;
+
+            fn zero() -> Self {
+                0
+            }
+
+            fn one() -> Self {
+                1
+            }
+
+            fn is_zero(&self) -> bool {
+                *self == 0
+            }
+
+            fn checked_add(&self, other: &Self) -> Option<Self> {
+                <$t>::checked_add(*self, *other)
+            }
+
+            fn divide(&self, weights: &[u64]) -> Option<Vec<Self>> {
+                let total = <$t>::from(weights.iter().sum::<u64>());
+                let mut remaining = *self;
+                let mut shares = weights
+                    .iter()
+                    .map(|&weight| {
+                        let share = self.checked_mul(<$t>::from(weight))?.checked_div(total)?;
+                        remaining -= share;
+                        Some(share)
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                if let Some(last) = shares.last_mut() {
+                    *last += remaining;
+                }
+                Some(shares)
+            }
+        }
+    )*};
+}
+
+integer_flow!(u64, u128);
+
+impl Flow for BigUint {
+    const CONSERVED: bool = false;
+
+    fn zero() -> Self {
+        BigUint::ZERO
+    }
+
+    fn one() -> Self {
+        BigUint::from(1u8)
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == BigUint::ZERO
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn divide(&self, weights: &[u64]) -> Option<Vec<Self>> {
+        let total: u64 = weights.iter().sum();
+        let mut remaining = self.clone();
+        let mut shares: Vec<Self> = weights
+            .iter()
+            .map(|&weight| {
+                let share = self * weight / total;
+                remaining -= &share;
+                share
             })
             .collect();
+        if let Some(last) = shares.last_mut() {
+            *last += remaining;
+        }
+        Some(shares)
+    }
+}
+
+impl Flow for f64 {
+    const CONSERVED: bool = true;
+
+    fn zero() -> Self {
+        0.0
+    }
+
+    fn one() -> Self {
+        1.0
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0.0
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
 
+    #[allow(clippy::cast_precision_loss)]
+    fn divide(&self, weights: &[u64]) -> Option<Vec<Self>> {
+        let total = weights.iter().sum::<u64>() as f64;
+        Some(
+            weights
+                .iter()
+                .map(|&weight| self * weight as f64 / total)
+                .collect(),
+        )
+    }
+}
+
+impl Flow for BigRational {
+    const CONSERVED: bool = true;
+
+    fn zero() -> Self {
+        BigRational::from_integer(BigInt::ZERO)
+    }
+
+    fn one() -> Self {
+        BigRational::from_integer(BigInt::from(1u8))
+    }
+
+    fn is_zero(&self) -> bool {
+        *self.numer() == BigInt::ZERO
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn divide(&self, weights: &[u64]) -> Option<Vec<Self>> {
+        let total: u64 = weights.iter().sum();
+        Some(
+            weights
+                .iter()
+                .map(|&weight| self * BigRational::new(BigInt::from(weight), BigInt::from(total)))
+                .collect(),
+        )
+    }
+}
+
+struct DiagramStep {
+    cells: Vec<DiagramCell>,
+}
+
+impl DiagramStep {
+    fn parse(line: usize, value: &str) -> Result<Self, DiagramError> {
+        let cells = value
+            .chars()
+            .enumerate()
+            .map(|(x, c)| {
+                DiagramCell::try_from(c).map_err(|c| DiagramError::UnknownCell {
+                    line,
+                    column: x + 1,
+                    c,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(DiagramStep { cells })
+    }
+}
+
+struct TachyonManifold<F> {
+    timelines: Vec<F>,
+    splitters: u64,
+    history: Vec<Vec<F>>,
+    lost_left: F,
+    lost_right: F,
+    weights: Weights,
+    /** Flow started by each source */
+    source: F,
+}
+
+impl<F: Flow> TachyonManifold<F> {
+    fn new(width: usize, weights: Weights, source: F) -> Self {
         TachyonManifold {
-            timelines,
+            timelines: vec![F::zero(); width],
             splitters: 0,
+            history: vec![],
+            lost_left: F::zero(),
+            lost_right: F::zero(),
+            weights,
+            source,
         }
     }
-}
 
-impl TachyonManifold {
-    fn step(&mut self, step: &DiagramStep) {
-        let mut new_timelines = vec![0; self.timelines.len()];
+    /**
+     * Every `S`, on any row, starts one more timeline below itself
+     * A probability mass of 1 is shared evenly between the sources, so exits add up to 1
+     */
+    fn run(input: &[String], weights: Weights) -> Result<Self, DiagramError> {
+        if input.is_empty() {
+            return Err(DiagramError::Empty);
+        }
+        let width = input
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+        let sources = input
+            .iter()
+            .flat_map(|line| line.chars())
+            .filter(|&c| c == 'S')
+            .count();
+        let source = if F::CONSERVED && sources > 1 {
+            F::one()
+                .divide(&vec![1; sources])
+                .expect("probability flows always divide")
+                .swap_remove(0)
+        } else {
+            F::one()
+        };
+        let mut tachyon_manifold = TachyonManifold::new(width, weights, source);
 
-        for (i, &count) in self.timelines.iter().enumerate().filter(|&(_, &c)| c > 0) {
-            match step.cells[i] {
-                DiagramCell::Splitter => {
-                    self.splitters += 1;
+        for (y, line) in input.iter().enumerate() {
+            tachyon_manifold
+                .step(&DiagramStep::parse(y + 1, line)?)
+                .ok_or(DiagramError::Overflow { line: y + 1 })?;
+        }
 
-                    if i > 0 {
-                        new_timelines[i - 1] += count;
-                    }
-                    if i + 1 < new_timelines.len() {
-                        new_timelines[i + 1] += count;
-                    }
+        Ok(tachyon_manifold)
+    }
+
+    fn step(&mut self, step: &DiagramStep) -> Option<()> {
+        let mut new_timelines = vec![F::zero(); self.timelines.len()];
+
+        for (i, flow) in self
+            .timelines
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| !f.is_zero())
+        {
+            let rule = step
+                .cells
+                .get(i)
+                .copied()
+                .unwrap_or(DiagramCell::Empty)
+                .rule();
+            if rule.splits {
+                self.splitters += 1;
+            }
+
+            let shares = match rule.transfer {
+                Transfer::Duplicate if !F::CONSERVED || rule.outputs.len() < 2 => {
+                    vec![flow.clone(); rule.outputs.len()]
+                }
+                _ => {
+                    let weights: Vec<u64> =
+                        rule.outputs.iter().map(|&o| self.weights.of(o)).collect();
+                    flow.divide(&weights)?
                 }
-                DiagramCell::Empty => new_timelines[i] += count,
+            };
+
+            for (&offset, share) in rule.outputs.iter().zip(shares) {
+                let lost = match i.checked_add_signed(offset) {
+                    None => &mut self.lost_left,
+                    Some(j) if j >= new_timelines.len() => &mut self.lost_right,
+                    Some(j) => &mut new_timelines[j],
+                };
+                *lost = lost.checked_add(&share)?;
             }
         }
 
+        for (x, cell) in step.cells.iter().enumerate() {
+            if let DiagramCell::Source = cell {
+                new_timelines[x] = new_timelines[x].checked_add(&self.source)?;
+            }
+        }
+
+        self.history.push(new_timelines.clone());
         self.timelines = new_timelines;
+        Some(())
+    }
+
+    fn total(&self) -> Option<F> {
+        self.timelines
+            .iter()
+            .try_fold(F::zero(), |total, flow| total.checked_add(flow))
     }
 }
 
+/**
+ * Draws the diagram with beams as `|`, one row of `history` per input line,
+ * shorter lines padded with empty cells up to the widest one
+ * Timelines are counted exactly so that any diagram renders
+ */
+struct Rendering<'a> {
+    diagram: &'a [String],
+    history: &'a [Vec<BigUint>],
+    heatmap: bool,
+    width: usize,
+    /** Logarithm of the busiest cell, the top of the heatmap scale */
+    max_log2: f64,
+}
+
+#[derive(Clone, Copy)]
+enum RenderedCell<'a> {
+    Source,
+    Beam(&'a BigUint),
+    Device { symbol: char, hit: bool },
+    Empty,
+}
+
+type Rgb = (u8, u8, u8);
+
+const CELL_SIZE: usize = 8;
+const BACKGROUND: Rgb = (15, 15, 35);
+const BEAM: Rgb = (255, 220, 0);
+const DEVICE_HIT: Rgb = (230, 40, 40);
+const DEVICE_MISSED: Rgb = (110, 110, 110);
+const SOURCE: Rgb = (40, 200, 80);
+
+impl<'a> Rendering<'a> {
+    fn from(diagram: &'a [String], manifold: &'a TachyonManifold<BigUint>, heatmap: bool) -> Self {
+        Rendering {
+            diagram,
+            history: &manifold.history,
+            heatmap,
+            width: manifold.timelines.len(),
+            max_log2: manifold.history.iter().flatten().max().map_or(0.0, log2),
+        }
+    }
+
+    fn cells(&self) -> impl Iterator<Item = Vec<RenderedCell<'a>>> + '_ {
+        self.diagram.iter().enumerate().map(|(y, line)| {
+            line.chars()
+                .chain(iter::repeat('.'))
+                .take(self.width)
+                .enumerate()
+                .map(|(x, c)| match c {
+                    'S' => RenderedCell::Source,
+                    '.' if !self.history[y][x].is_zero() => RenderedCell::Beam(&self.history[y][x]),
+                    '.' => RenderedCell::Empty,
+                    symbol => RenderedCell::Device {
+                        symbol,
+                        hit: y > 0 && !self.history[y - 1][x].is_zero(),
+                    },
+                })
+                .collect()
+        })
+    }
+
+    fn color(&self, cell: RenderedCell) -> Rgb {
+        match cell {
+            RenderedCell::Source => SOURCE,
+            RenderedCell::Beam(count) if self.heatmap => self.heat(count),
+            RenderedCell::Beam(_) => BEAM,
+            RenderedCell::Device { hit: true, .. } => DEVICE_HIT,
+            RenderedCell::Device { hit: false, .. } => DEVICE_MISSED,
+            RenderedCell::Empty => BACKGROUND,
+        }
+    }
+
+    /**
+     * Blue for a single timeline up to red for the busiest cell, on a log scale
+     */
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn heat(&self, count: &BigUint) -> Rgb {
+        let ratio = if self.max_log2 > 0.0 {
+            log2(count) / self.max_log2
+        } else {
+            0.0
+        };
+        let red = (255.0 * ratio).round() as u8;
+        (red, 64, 255 - red)
+    }
+
+    fn to_ansi(&self) -> String {
+        let mut output = String::new();
+        for row in self.cells() {
+            for cell in row {
+                let c = match cell {
+                    RenderedCell::Source => 'S',
+                    RenderedCell::Beam(_) => '|',
+                    RenderedCell::Device { symbol, .. } => symbol,
+                    RenderedCell::Empty => '.',
+                };
+                let (r, g, b) = self.color(cell);
+                let _ = write!(output, "\x1b[38;2;{r};{g};{b}m{c}");
+            }
+            output.push_str("\x1b[0m\n");
+        }
+        output
+    }
+
+    fn to_svg(&self) -> String {
+        let height = self.diagram.len() * CELL_SIZE;
+        let width = self.width * CELL_SIZE;
+        let (r, g, b) = BACKGROUND;
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"rgb({r},{g},{b})\"/>\n"
+        );
+        for (y, row) in self.cells().enumerate() {
+            for (x, cell) in row.into_iter().enumerate() {
+                let (r, g, b) = self.color(cell);
+                let (px, py) = (x * CELL_SIZE, y * CELL_SIZE);
+                let _ = match cell {
+                    RenderedCell::Empty => Ok(()),
+                    RenderedCell::Beam(count) => writeln!(
+                        svg,
+                        "<rect x=\"{}\" y=\"{py}\" width=\"2\" height=\"{CELL_SIZE}\" fill=\"rgb({r},{g},{b})\"><title>{count}</title></rect>",
+                        px + CELL_SIZE / 2 - 1
+                    ),
+                    RenderedCell::Device { symbol: '^', .. } => writeln!(
+                        svg,
+                        "<polygon points=\"{},{} {},{} {},{}\" fill=\"rgb({r},{g},{b})\"/>",
+                        px + CELL_SIZE / 2,
+                        py,
+                        px,
+                        py + CELL_SIZE,
+                        px + CELL_SIZE,
+                        py + CELL_SIZE
+                    ),
+                    RenderedCell::Device { .. } => writeln!(
+                        svg,
+                        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"rgb({r},{g},{b})\"/>",
+                        px + 1,
+                        py + 1,
+                        CELL_SIZE - 2,
+                        CELL_SIZE - 2
+                    ),
+                    RenderedCell::Source => writeln!(
+                        svg,
+                        "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"rgb({r},{g},{b})\"/>",
+                        px + CELL_SIZE / 2,
+                        py + CELL_SIZE / 2,
+                        CELL_SIZE / 2
+                    ),
+                };
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /**
+     * Binary PPM (P6), one `CELL_SIZE` square per cell
+     */
+    fn to_ppm(&self) -> Vec<u8> {
+        let rows: Vec<Vec<RenderedCell>> = self.cells().collect();
+        let width = self.width * CELL_SIZE;
+        let height = rows.len() * CELL_SIZE;
+
+        let mut ppm = format!("P6\n{width} {height}\n255\n").into_bytes();
+        for row in &rows {
+            for _ in 0..CELL_SIZE {
+                for cell in row {
+                    let (r, g, b) = self.color(*cell);
+                    for _ in 0..CELL_SIZE {
+                        ppm.extend([r, g, b]);
+                    }
+                }
+            }
+        }
+        ppm
+    }
+}
+
+/**
+ * Base 2 logarithm from the 64 most significant bits, precise enough for colours
+ */
+#[allow(clippy::cast_precision_loss)]
+fn log2(n: &BigUint) -> f64 {
+    let shift = n.bits().saturating_sub(64);
+    let top = u64::try_from(n >> shift).unwrap_or(u64::MAX);
+    (top as f64).log2() + shift as f64
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -129,7 +818,7 @@ mod test {
             "...".to_string(),
         ];
         let result = p1(&input);
-        assert_eq!(result, 0);
+        assert_eq!(result, Ok(0));
     }
 
     #[test]
@@ -141,7 +830,7 @@ mod test {
             "...".to_string(),
         ];
         let result = p1(&input);
-        assert_eq!(result, 1);
+        assert_eq!(result, Ok(1));
     }
 
     #[test]
@@ -155,7 +844,7 @@ mod test {
             ".....".to_string(),
         ];
         let result = p1(&input);
-        assert_eq!(result, 3);
+        assert_eq!(result, Ok(3));
     }
 
     #[test]
@@ -169,7 +858,7 @@ mod test {
             "......".to_string(),
         ];
         let result = p1(&input);
-        assert_eq!(result, 2);
+        assert_eq!(result, Ok(2));
     }
 
     #[test]
@@ -193,7 +882,7 @@ mod test {
             "...............".to_string(),
         ];
         let result = p1(&input);
-        assert_eq!(result, 21);
+        assert_eq!(result, Ok(21));
     }
 
     #[test]
@@ -205,7 +894,7 @@ mod test {
             "...".to_string(),
         ];
         let result = p2(&input);
-        assert_eq!(result, 1);
+        assert_eq!(result, Ok(1));
     }
 
     #[test]
@@ -217,7 +906,7 @@ mod test {
             "...".to_string(),
         ];
         let result = p2(&input);
-        assert_eq!(result, 2);
+        assert_eq!(result, Ok(2));
     }
 
     #[test]
@@ -231,7 +920,7 @@ mod test {
             ".....".to_string(),
         ];
         let result = p2(&input);
-        assert_eq!(result, 4);
+        assert_eq!(result, Ok(4));
     }
 
     #[test]
@@ -245,7 +934,7 @@ mod test {
             "......".to_string(),
         ];
         let result = p2(&input);
-        assert_eq!(result, 3);
+        assert_eq!(result, Ok(3));
     }
 
     #[test]
@@ -269,6 +958,250 @@ mod test {
             "...............".to_string(),
         ];
         let result = p2(&input);
-        assert_eq!(result, 40);
+        assert_eq!(result, Ok(40));
+    }
+
+    #[test]
+    fn render_beams() {
+        let input = vec![
+            "..S..".to_string(),
+            ".....".to_string(),
+            "..^..".to_string(),
+            ".....".to_string(),
+            ".^.^.".to_string(),
+            ".....".to_string(),
+        ];
+        let manifold = TachyonManifold::run(&input, Weights::default()).unwrap();
+        let rendering = Rendering::from(&input, &manifold, false);
+        let plain: Vec<String> = rendering
+            .cells()
+            .map(|row| {
+                row.into_iter()
+                    .map(|cell| match cell {
+                        RenderedCell::Source => 'S',
+                        RenderedCell::Beam(count) => {
+                            char::from_digit(u32::try_from(count).unwrap(), 10).unwrap()
+                        }
+                        RenderedCell::Device { hit: true, .. } => '^',
+                        RenderedCell::Device { hit: false, .. } => 'x',
+                        RenderedCell::Empty => '.',
+                    })
+                    .collect()
+            })
+            .collect();
+
+        assert_eq!(
+            plain,
+            vec!["..S..", "..1..", ".1^1.", ".1.1.", "1^2^1", "1.2.1"]
+        );
+    }
+
+    #[test]
+    fn render_ppm_header() {
+        let input = vec![".S.".to_string(), ".^.".to_string()];
+        let manifold = TachyonManifold::run(&input, Weights::default()).unwrap();
+        let ppm = Rendering::from(&input, &manifold, true).to_ppm();
+
+        assert!(ppm.starts_with(b"P6\n24 16\n255\n"));
+        assert_eq!(ppm.len(), "P6\n24 16\n255\n".len() + 24 * 16 * 3);
+    }
+
+    #[test]
+    fn render_ragged_lines() {
+        let input = vec![".S".to_string(), ".^.".to_string(), "...".to_string()];
+        let manifold = TachyonManifold::run(&input, Weights::default()).unwrap();
+        let rendering = Rendering::from(&input, &manifold, true);
+
+        assert!(rendering.cells().all(|row| row.len() == 3));
+        assert!(matches!(
+            rendering.cells().next().unwrap()[2],
+            RenderedCell::Empty
+        ));
+        assert!(
+            rendering.to_svg().starts_with(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"24\" height=\"24\">"
+            )
+        );
+        let ppm = rendering.to_ppm();
+        assert_eq!(ppm.len(), "P6\n24 24\n255\n".len() + 24 * 24 * 3);
+    }
+
+    #[test]
+    fn p2_absorbers_and_deflectors() {
+        let input = vec![
+            "...S...".to_string(),
+            "...^...".to_string(),
+            "../.\\..".to_string(),
+            ".#...*.".to_string(),
+            ".......".to_string(),
+        ];
+        assert_eq!(p1(&input), Ok(2));
+        assert_eq!(p2(&input), Ok(3)); // Left beam absorbed, right beam split three ways
+    }
+
+    #[test]
+    fn p2_weighted_splitter_divides() {
+        let input = vec![
+            "..S..".to_string(),
+            "..*..".to_string(),
+            ".%%%.".to_string(),
+            ".....".to_string(),
+        ];
+        assert_eq!(p2(&input), Ok(3)); // Each of the three timelines is divided, not doubled
+
+        let report = exits::<u64>(&input, Weights::default()).unwrap();
+        assert_eq!(report.columns, vec![0, 0, 1, 1, 1]); // The rounded-down left shares are 0
+        assert_eq!(5_u64.divide(&[1, 1]), Some(vec![2, 3]));
+        assert_eq!(7_u64.divide(&[3, 0, 1]), Some(vec![5, 0, 2]));
+    }
+
+    #[test]
+    fn unknown_cell() {
+        let input = vec![".S.".to_string(), ".?.".to_string()];
+        assert_eq!(
+            p2(&input),
+            Err(DiagramError::UnknownCell {
+                line: 2,
+                column: 2,
+                c: '?'
+            })
+        );
+    }
+
+    #[test]
+    fn p2_overflow_is_reported() {
+        let mut input = vec![".S.".to_string()];
+        for _ in 0..64 {
+            input.push("*.*".to_string());
+            input.push(".*.".to_string());
+        }
+        assert_eq!(p2(&input), Err(DiagramError::Overflow { line: 70 }));
+
+        let report = exits::<BigUint>(&input, Weights::default()).unwrap();
+        assert_eq!(
+            report.total.to_string(),
+            "3173519642396569818983579456633697153"
+        );
+    }
+
+    #[test]
+    fn overflowing_diagram_in_wider_modes() {
+        let mut input = vec![".S.".to_string()];
+        for _ in 0..64 {
+            input.push("*.*".to_string());
+            input.push(".*.".to_string());
+        }
+        let total = "Total: 3173519642396569818983579456633697153\n";
+
+        assert_eq!(
+            exit_report(&input, None, Weights::default()),
+            Err(DiagramError::Overflow { line: 70 })
+        );
+        assert!(
+            exit_report(&input, Some("u128"), Weights::default())
+                .unwrap()
+                .ends_with(total)
+        );
+        assert!(
+            exit_report(&input, Some("big"), Weights::default())
+                .unwrap()
+                .ends_with(total)
+        );
+        assert_eq!(p1(&input), Ok(190));
+
+        let manifold = TachyonManifold::run(&input, Weights::default()).unwrap();
+        let rendering = Rendering::from(&input, &manifold, true);
+        assert_eq!(rendering.to_ansi().lines().count(), input.len());
+        assert_eq!(
+            rendering.heat(manifold.history.iter().flatten().max().unwrap()),
+            (255, 64, 0)
+        );
+    }
+
+    #[test]
+    fn exit_probabilities() {
+        let input = vec![
+            "..S..".to_string(),
+            "..^..".to_string(),
+            ".^.#.".to_string(),
+            ".....".to_string(),
+        ];
+        let report = exits::<BigRational>(&input, Weights::default()).unwrap();
+        let columns: Vec<String> = report.columns.iter().map(ToString::to_string).collect();
+        assert_eq!(columns, vec!["1/4", "0", "1/4", "0", "0"]);
+        assert_eq!(report.total.to_string(), "1/2");
+
+        let weights = "3,0,1".parse().unwrap();
+        let report = exits::<f64>(&input, weights).unwrap();
+        assert_eq!(report.columns, vec![0.5625, 0.0, 0.1875, 0.0, 0.0]);
+        assert!((report.total - 0.75).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn exit_probabilities_several_sources() {
+        let input = vec![
+            "S.S..".to_string(),
+            "..^..".to_string(),
+            "S....".to_string(),
+            ".....".to_string(),
+        ];
+        let report = exits::<BigRational>(&input, Weights::default()).unwrap();
+        let columns: Vec<String> = report.columns.iter().map(ToString::to_string).collect();
+        assert_eq!(columns, vec!["2/3", "1/6", "0", "1/6", "0"]);
+        assert_eq!(report.total.to_string(), "1");
+
+        let report = exits::<u64>(&input, Weights::default()).unwrap();
+        assert_eq!(report.columns, vec![2, 1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn unknown_mode() {
+        let input = vec!["S".to_string(), ".".to_string()];
+        assert_eq!(
+            exit_report(&input, Some("rationl"), Weights::default()),
+            Err(DiagramError::UnknownMode("rationl".to_string()))
+        );
+        assert_eq!(
+            exit_report(&input, Some("u64"), Weights::default()),
+            exit_report(&input, None, Weights::default())
+        );
+    }
+
+    #[test]
+    fn invalid_weights() {
+        assert_eq!(
+            "0,1,0".parse::<Weights>().err(),
+            Some(DiagramError::InvalidWeights)
+        );
+        assert_eq!(
+            "1,1".parse::<Weights>().err(),
+            Some(DiagramError::InvalidWeights)
+        );
+        assert_eq!(
+            "18446744073709551615,1,0".parse::<Weights>().err(),
+            Some(DiagramError::InvalidWeights)
+        );
+        assert!("18446744073709551614,1,0".parse::<Weights>().is_ok());
+    }
+
+    #[test]
+    fn p2_several_sources() {
+        let input = vec![
+            "S...S".to_string(),
+            "^....".to_string(),
+            "..S.^".to_string(),
+            "..^..".to_string(),
+            ".....".to_string(),
+        ];
+        assert_eq!(p2(&input), Ok(4));
+
+        let report = exits::<u64>(&input, Weights::default()).unwrap();
+        assert_eq!(report.columns, vec![0, 2, 0, 2, 0]);
+        assert_eq!(report.lost_left, 1);
+        assert_eq!(report.lost_right, 1);
+        assert_eq!(
+            report.to_string(),
+            "Column 2: 2\nColumn 4: 2\nLost left: 1\nLost right: 1\nTotal: 4\n"
+        );
     }
 }
@@ -1,20 +1,53 @@
 use std::{
-    fs::File,
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    env,
+    error::Error,
+    fmt::{self, Write},
+    fs::{self, File},
     io::{self, BufRead, BufReader},
     mem::swap,
+    str::FromStr,
 };
 
 fn main() -> io::Result<()> {
     let input = read_input()?;
 
-    let p1_result = p1(&input, 1000);
+    let args: Vec<String> = env::args().collect();
+    let metric = option_value(&args, "--metric")
+        .map_or(Ok(Metric::Euclidean), str::parse)
+        .map_err(invalid_data)?;
+
+    let p1_result = p1(&input, 1000, metric).map_err(invalid_data)?;
 
     println!("Part 1 result: {p1_result}");
 
-    let p2_result = p2(&input);
+    let p2_result = p2(&input, metric).map_err(invalid_data)?;
 
     println!("Part 2 result: {p2_result}");
 
+    if let Some(path) = option_value(&args, "--mst") {
+        let junction_boxes = parse_junction_boxes(&input).map_err(invalid_data)?;
+        let spanning_tree = SpanningTree::from(&junction_boxes, metric);
+        fs::write(path, spanning_tree.to_csv())?;
+        println!("Spanning tree weight: {}", spanning_tree.total_weight());
+    }
+
+    if let Some(threshold) = option_value(&args, "--threshold") {
+        let threshold = threshold
+            .parse::<f64>()
+            .map_err(|_| invalid_data(JunctionBoxError::InvalidThreshold(threshold.to_string())))?;
+        let junction_boxes = parse_junction_boxes(&input).map_err(invalid_data)?;
+        let clustering = Clustering::from(&junction_boxes, metric, threshold);
+        println!("Clusters: {}", clustering.clusters.len());
+        if let Some(path) = option_value(&args, "--labels") {
+            fs::write(path, clustering.labels_csv())?;
+        }
+        if let Some(path) = option_value(&args, "--clusters") {
+            fs::write(path, clustering.clusters_csv())?;
+        }
+    }
+
     Ok(())
 }
 
@@ -23,83 +56,612 @@ fn read_input() -> io::Result<Vec<String>> {
     BufReader::new(file).lines().collect::<Result<_, _>>()
 }
 
-fn p1(input: &[String], max_connections: usize) -> usize {
-    let junction_boxes = input.iter().map(JunctionBox::from).collect::<Vec<_>>();
+fn invalid_data(error: JunctionBoxError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn option_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+fn p1(input: &[String], max_connections: usize, metric: Metric) -> Result<usize, JunctionBoxError> {
+    let junction_boxes = parse_junction_boxes(input)?;
     let mut circuits = Circuits::from(junction_boxes.len());
-    let mut connections = Connections::from(&junction_boxes);
 
-    connections.sort_by_distance();
+    for (a, b, _) in Connections::from(&junction_boxes, metric).take(max_connections) {
+        circuits.connect(a, b);
+    }
+
+    Ok(circuits.iter_by_size().rev().take(3).product::<usize>())
+}
+
+fn p2(input: &[String], metric: Metric) -> Result<u64, JunctionBoxError> {
+    let junction_boxes = parse_junction_boxes(input)?;
+
+    Ok(SpanningTree::from(&junction_boxes, metric)
+        .edges
+        .last()
+        .map_or(0, |edge| {
+            junction_boxes[edge.a].coordinates[0] * junction_boxes[edge.b].coordinates[0]
+        }))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum JunctionBoxError {
+    InvalidCoordinate {
+        line: usize,
+        value: String,
+    },
+    DimensionMismatch {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    UnknownMetric(String),
+    InvalidThreshold(String),
+}
+
+impl fmt::Display for JunctionBoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JunctionBoxError::InvalidCoordinate { line, value } => {
+                write!(f, "invalid coordinate {value:?} at line {line}")
+            }
+            JunctionBoxError::DimensionMismatch {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {line} has {found} coordinates, {expected} expected"
+            ),
+            JunctionBoxError::UnknownMetric(metric) => write!(f, "unknown metric {metric:?}"),
+            JunctionBoxError::InvalidThreshold(threshold) => {
+                write!(f, "invalid threshold {threshold:?}")
+            }
+        }
+    }
+}
+
+impl Error for JunctionBoxError {}
 
-    for (a, b, _) in connections.0.iter().take(max_connections) {
-        circuits.connect(*a, *b);
+/**
+ * Every line must have as many coordinates as the first one
+ */
+fn parse_junction_boxes(input: &[String]) -> Result<Vec<JunctionBox>, JunctionBoxError> {
+    let junction_boxes = input
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            line.parse::<JunctionBox>()
+                .map_err(|value| JunctionBoxError::InvalidCoordinate { line: i + 1, value })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let expected = junction_boxes.first().map_or(0, |b| b.coordinates.len());
+    if let Some(i) = junction_boxes
+        .iter()
+        .position(|b| b.coordinates.len() != expected)
+    {
+        return Err(JunctionBoxError::DimensionMismatch {
+            line: i + 1,
+            expected,
+            found: junction_boxes[i].coordinates.len(),
+        });
     }
 
-    circuits.iter_by_size().rev().take(3).product::<usize>()
+    Ok(junction_boxes)
 }
 
-fn p2(input: &[String]) -> u64 {
-    let junction_boxes = input.iter().map(JunctionBox::from).collect::<Vec<_>>();
-    let mut circuits = Circuits::from(junction_boxes.len());
-    let mut connections = Connections::from(&junction_boxes);
+/**
+ * Connections are ordered by `Metric::key`, which for Euclidean is the squared distance
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Metric {
+    Euclidean,
+    Manhattan,
+    Chebyshev,
+}
+
+impl FromStr for Metric {
+    type Err = JunctionBoxError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "euclidean" => Ok(Metric::Euclidean),
+            "manhattan" => Ok(Metric::Manhattan),
+            "chebyshev" => Ok(Metric::Chebyshev),
+            _ => Err(JunctionBoxError::UnknownMetric(s.to_string())),
+        }
+    }
+}
 
-    connections.sort_by_distance();
+impl Metric {
+    fn key(self, gaps: impl Iterator<Item = u64>) -> u64 {
+        match self {
+            Metric::Euclidean => gaps.map(|gap| gap * gap).sum(),
+            Metric::Manhattan => gaps.sum(),
+            Metric::Chebyshev => gaps.max().unwrap_or(0),
+        }
+    }
 
-    for (index, (a, b, _)) in connections.0.iter().enumerate() {
-        circuits.connect(*a, *b);
-        // At least n-1 connections are needed to connect n junction boxes
-        if index >= junction_boxes.len() - 1 && circuits.is_all_connected() {
-            return (junction_boxes[*a].x) * (junction_boxes[*b].x);
+    #[allow(clippy::cast_precision_loss)]
+    fn distance(self, key: u64) -> f64 {
+        match self {
+            Metric::Euclidean => (key as f64).sqrt(),
+            Metric::Manhattan | Metric::Chebyshev => key as f64,
         }
     }
 
-    0
+    fn squared_distance(self, key: u64) -> u128 {
+        match self {
+            Metric::Euclidean => u128::from(key),
+            Metric::Manhattan | Metric::Chebyshev => u128::from(key) * u128::from(key),
+        }
+    }
 }
 
 struct JunctionBox {
-    x: u64,
-    y: u64,
-    z: u64,
-}
-
-impl From<&String> for JunctionBox {
-    fn from(value: &String) -> Self {
-        let nums: Vec<u64> = value.split(',').map(|num| num.parse().unwrap()).collect();
-        JunctionBox {
-            x: nums[0],
-            y: nums[1],
-            z: nums[2],
-        }
+    coordinates: Vec<u64>,
+}
+
+impl FromStr for JunctionBox {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coordinates = s
+            .split(',')
+            .map(|num| num.trim().parse().map_err(|_| num.to_string()))
+            .collect::<Result<_, _>>()?;
+
+        Ok(JunctionBox { coordinates })
     }
 }
 
 impl JunctionBox {
-    fn distance(&self, other: &JunctionBox) -> u64 {
-        let dx = self.x.abs_diff(other.x);
-        let dy = self.y.abs_diff(other.y);
-        let dz = self.z.abs_diff(other.z);
-        dx * dx + dy * dy + dz * dz
+    fn distance(&self, other: &JunctionBox, metric: Metric) -> u64 {
+        metric.key(
+            self.coordinates
+                .iter()
+                .zip(other.coordinates.iter())
+                .map(|(a, b)| a.abs_diff(*b)),
+        )
+    }
+
+    /**
+     * Lower bound of the distance to any junction box inside `node`
+     */
+    fn distance_to(&self, node: &KdNode, metric: Metric) -> u64 {
+        metric.key(
+            self.coordinates
+                .iter()
+                .zip(node.min.iter().zip(node.max.iter()))
+                .map(|(&c, (&min, &max))| min.saturating_sub(c).max(c.saturating_sub(max))),
+        )
+    }
+}
+
+const LEAF_SIZE: usize = 8;
+
+struct KdNode {
+    start: usize,
+    end: usize,
+    min: Vec<u64>,
+    max: Vec<u64>,
+    children: Option<(usize, usize)>,
+}
+
+/**
+ * k-d tree over junction box indices, split on the widest axis of each node
+ */
+struct KdTree<'a> {
+    junction_boxes: &'a [JunctionBox],
+    metric: Metric,
+    order: Vec<usize>,
+    nodes: Vec<KdNode>,
+}
+
+impl<'a> KdTree<'a> {
+    fn from(junction_boxes: &'a [JunctionBox], metric: Metric) -> Self {
+        let mut tree = KdTree {
+            junction_boxes,
+            metric,
+            order: (0..junction_boxes.len()).collect(),
+            nodes: vec![],
+        };
+        if !junction_boxes.is_empty() {
+            tree.build(0, junction_boxes.len());
+        }
+        tree
+    }
+
+    fn build(&mut self, start: usize, end: usize) -> usize {
+        let dimensions = self.junction_boxes[0].coordinates.len();
+        let mut min = vec![u64::MAX; dimensions];
+        let mut max = vec![0; dimensions];
+        for &i in &self.order[start..end] {
+            for (axis, &c) in self.junction_boxes[i].coordinates.iter().enumerate() {
+                min[axis] = min[axis].min(c);
+                max[axis] = max[axis].max(c);
+            }
+        }
+
+        let widest = (0..dimensions).max_by_key(|&axis| max[axis] - min[axis]);
+        let index = self.nodes.len();
+        self.nodes.push(KdNode {
+            start,
+            end,
+            min,
+            max,
+            children: None,
+        });
+
+        if let Some(axis) = widest.filter(|_| end - start > LEAF_SIZE) {
+            let mid = start + (end - start) / 2;
+            let junction_boxes = self.junction_boxes;
+            self.order[start..end]
+                .select_nth_unstable_by_key(mid - start, |&i| junction_boxes[i].coordinates[axis]);
+
+            let left = self.build(start, mid);
+            let right = self.build(mid, end);
+            self.nodes[index].children = Some((left, right));
+        }
+
+        index
+    }
+
+    /**
+     * Circuit shared by every junction box below each node, None when they are in several
+     * Children come after their parent, so a reverse walk sees them first
+     */
+    fn node_circuits(&self, circuits: &[usize]) -> Vec<Option<usize>> {
+        let mut node_circuits = vec![None; self.nodes.len()];
+        for (id, node) in self.nodes.iter().enumerate().rev() {
+            node_circuits[id] = match node.children {
+                Some((left, right)) if node_circuits[left] == node_circuits[right] => {
+                    node_circuits[left]
+                }
+                Some(_) => None,
+                None => {
+                    let first = circuits[self.order[node.start]];
+                    self.order[node.start..node.end]
+                        .iter()
+                        .all(|&i| circuits[i] == first)
+                        .then_some(first)
+                }
+            };
+        }
+        node_circuits
+    }
+
+    /**
+     * Improves `best`, as `(distance, a, b)` with `a < b`, with the nearest junction box
+     * to `origin` in another circuit, skipping the nodes inside its own circuit
+     */
+    fn nearest_outside(
+        &self,
+        origin: usize,
+        circuits: &[usize],
+        node_circuits: &[Option<usize>],
+        best: &mut Option<(u64, usize, usize)>,
+    ) {
+        let junction_box = &self.junction_boxes[origin];
+        let circuit = circuits[origin];
+        let mut stack = vec![0];
+
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id];
+            if node_circuits[id] == Some(circuit)
+                || best.is_some_and(|(distance, _, _)| {
+                    junction_box.distance_to(node, self.metric) > distance
+                })
+            {
+                continue;
+            }
+
+            match node.children {
+                Some((left, right)) => {
+                    let distance =
+                        |child: usize| junction_box.distance_to(&self.nodes[child], self.metric);
+                    // The nearest child is explored first
+                    if distance(left) <= distance(right) {
+                        stack.extend([right, left]);
+                    } else {
+                        stack.extend([left, right]);
+                    }
+                }
+                None => {
+                    for &j in &self.order[node.start..node.end] {
+                        if circuits[j] != circuit {
+                            let distance =
+                                junction_box.distance(&self.junction_boxes[j], self.metric);
+                            let candidate = (distance, origin.min(j), origin.max(j));
+                            if best.is_none_or(|best| candidate < best) {
+                                *best = Some(candidate);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+const NODE: u8 = 0;
+const POINT: u8 = 1;
+
+/**
+ * Best-first traversal yielding the neighbors of `origin` by increasing distance
+ * Nodes come before points at equal distance, so ties are yielded by index
+ */
+struct Neighbors {
+    origin: usize,
+    queue: BinaryHeap<Reverse<(u64, u8, usize)>>,
+}
+
+impl Neighbors {
+    fn from(origin: usize) -> Self {
+        Neighbors {
+            origin,
+            queue: BinaryHeap::from([Reverse((0, NODE, 0))]),
+        }
+    }
+
+    fn next(&mut self, tree: &KdTree) -> Option<(u64, usize)> {
+        let origin = &tree.junction_boxes[self.origin];
+
+        while let Some(Reverse((distance, kind, id))) = self.queue.pop() {
+            if kind == POINT {
+                return Some((distance, id));
+            }
+
+            let node = &tree.nodes[id];
+            match node.children {
+                Some((left, right)) => {
+                    for child in [left, right] {
+                        let distance = origin.distance_to(&tree.nodes[child], tree.metric);
+                        self.queue.push(Reverse((distance, NODE, child)));
+                    }
+                }
+                None => {
+                    for &j in &tree.order[node.start..node.end] {
+                        // Each pair is only yielded from its lowest index
+                        if j > self.origin {
+                            let distance = origin.distance(&tree.junction_boxes[j], tree.metric);
+                            self.queue.push(Reverse((distance, POINT, j)));
+                        }
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/**
+ * Lazily yields every pair `(a, b, distance)` with `a < b`, by increasing distance then indices
+ */
+struct Connections<'a> {
+    tree: KdTree<'a>,
+    neighbors: Vec<Neighbors>,
+    queue: BinaryHeap<Reverse<(u64, usize, usize)>>,
+}
+
+impl<'a> Connections<'a> {
+    fn from(value: &'a [JunctionBox], metric: Metric) -> Self {
+        let tree = KdTree::from(value, metric);
+        let mut neighbors: Vec<Neighbors> = (0..value.len()).map(Neighbors::from).collect();
+        let queue = neighbors
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, n)| n.next(&tree).map(|(distance, j)| Reverse((distance, i, j))))
+            .collect();
+
+        Connections {
+            tree,
+            neighbors,
+            queue,
+        }
+    }
+}
+
+impl Iterator for Connections<'_> {
+    type Item = (usize, usize, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((distance, a, b)) = self.queue.pop()?;
+        if let Some((next_distance, next)) = self.neighbors[a].next(&self.tree) {
+            self.queue.push(Reverse((next_distance, a, next)));
+        }
+        Some((a, b, distance))
     }
 }
 
-struct Connections(Vec<(usize, usize, u64)>);
+struct SpanningTreeEdge {
+    a: usize,
+    b: usize,
+    squared_distance: u128,
+    distance: f64,
+    circuits: usize,
+    largest_circuit: usize,
+}
+
+/**
+ * Edges in the order Kruskal would accept the connections, with the circuit count
+ * and largest circuit size after each one
+ */
+struct SpanningTree {
+    edges: Vec<SpanningTreeEdge>,
+}
+
+impl SpanningTree {
+    fn from(junction_boxes: &[JunctionBox], metric: Metric) -> Self {
+        let mut circuits = Circuits::from(junction_boxes.len());
+        let edges = Self::boruvka(junction_boxes, metric)
+            .into_iter()
+            .map(|(key, a, b)| {
+                circuits.connect(a, b);
+                SpanningTreeEdge {
+                    a,
+                    b,
+                    squared_distance: metric.squared_distance(key),
+                    distance: metric.distance(key),
+                    circuits: circuits.count,
+                    largest_circuit: circuits.largest,
+                }
+            })
+            .collect();
+
+        SpanningTree { edges }
+    }
 
-impl From<&Vec<JunctionBox>> for Connections {
-    fn from(value: &Vec<JunctionBox>) -> Self {
-        let mut connections = Vec::with_capacity((value.len() * (value.len() - 1)) / 2);
-        for (i, a_box) in value.iter().enumerate() {
-            for (j, b_box) in value.iter().enumerate().skip(i + 1) {
-                let distance = a_box.distance(b_box);
-                connections.push((i, j, distance));
+    /**
+     * Borůvka: each round connects every circuit to its nearest junction box outside,
+     * found on the k-d tree, which at least halves the number of circuits
+     * Ties are broken by indices like `Connections`, so the tree is the one Kruskal builds
+     */
+    fn boruvka(junction_boxes: &[JunctionBox], metric: Metric) -> Vec<(u64, usize, usize)> {
+        let tree = KdTree::from(junction_boxes, metric);
+        let mut circuits = Circuits::from(junction_boxes.len());
+        let mut edges = vec![];
+
+        while circuits.count > 1 {
+            let roots: Vec<usize> = (0..junction_boxes.len())
+                .map(|i| circuits.find(i))
+                .collect();
+            let node_circuits = tree.node_circuits(&roots);
+
+            let mut nearest = vec![None; junction_boxes.len()];
+            for (i, &root) in roots.iter().enumerate() {
+                tree.nearest_outside(i, &roots, &node_circuits, &mut nearest[root]);
+            }
+            for (key, a, b) in nearest.into_iter().flatten() {
+                if circuits.connect(a, b) {
+                    edges.push((key, a, b));
+                }
             }
         }
 
-        Connections(connections)
+        edges.sort_unstable();
+        edges
+    }
+
+    fn total_weight(&self) -> f64 {
+        self.edges.iter().map(|edge| edge.distance).sum()
+    }
+
+    fn to_csv(&self) -> String {
+        let mut csv = String::from("a,b,squared_distance,distance,circuits,largest_circuit\n");
+        for edge in &self.edges {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{}",
+                edge.a,
+                edge.b,
+                edge.squared_distance,
+                edge.distance,
+                edge.circuits,
+                edge.largest_circuit
+            );
+        }
+        csv
     }
 }
 
-impl Connections {
-    fn sort_by_distance(&mut self) {
-        self.0.sort_by_key(|&(_, _, distance)| distance);
+struct Cluster {
+    size: usize,
+    centroid: Vec<f64>,
+    min: Vec<u64>,
+    max: Vec<u64>,
+}
+
+/**
+ * Single-linkage clustering: every pair strictly closer than the threshold is connected
+ * Labels are numbered by first junction box
+ */
+struct Clustering {
+    labels: Vec<usize>,
+    clusters: Vec<Cluster>,
+}
+
+impl Clustering {
+    #[allow(clippy::cast_precision_loss)]
+    fn from(junction_boxes: &[JunctionBox], metric: Metric, threshold: f64) -> Self {
+        let mut circuits = Circuits::from(junction_boxes.len());
+        for (a, b, _) in Connections::from(junction_boxes, metric)
+            .take_while(|&(_, _, key)| metric.distance(key) < threshold)
+        {
+            circuits.connect(a, b);
+        }
+
+        let mut root_labels = HashMap::new();
+        let labels: Vec<usize> = (0..junction_boxes.len())
+            .map(|i| {
+                let next = root_labels.len();
+                *root_labels.entry(circuits.find(i)).or_insert(next)
+            })
+            .collect();
+
+        let dimensions = junction_boxes.first().map_or(0, |b| b.coordinates.len());
+        let mut clusters: Vec<Cluster> = (0..root_labels.len())
+            .map(|_| Cluster {
+                size: 0,
+                centroid: vec![0.0; dimensions],
+                min: vec![u64::MAX; dimensions],
+                max: vec![0; dimensions],
+            })
+            .collect();
+        for (junction_box, &label) in junction_boxes.iter().zip(labels.iter()) {
+            let cluster = &mut clusters[label];
+            cluster.size += 1;
+            for (axis, &c) in junction_box.coordinates.iter().enumerate() {
+                cluster.centroid[axis] += c as f64;
+                cluster.min[axis] = cluster.min[axis].min(c);
+                cluster.max[axis] = cluster.max[axis].max(c);
+            }
+        }
+        for cluster in &mut clusters {
+            for c in &mut cluster.centroid {
+                *c /= cluster.size as f64;
+            }
+        }
+
+        Clustering { labels, clusters }
+    }
+
+    fn labels_csv(&self) -> String {
+        let mut csv = String::from("index,label\n");
+        for (index, label) in self.labels.iter().enumerate() {
+            let _ = writeln!(csv, "{index},{label}");
+        }
+        csv
+    }
+
+    fn clusters_csv(&self) -> String {
+        let dimensions = self.clusters.first().map_or(0, |c| c.centroid.len());
+        let mut csv = String::from("label,size");
+        for prefix in ["centroid", "min", "max"] {
+            for axis in 0..dimensions {
+                let _ = write!(csv, ",{prefix}_{axis}");
+            }
+        }
+        csv.push('\n');
+
+        for (label, cluster) in self.clusters.iter().enumerate() {
+            let _ = write!(csv, "{label},{}", cluster.size);
+            for c in &cluster.centroid {
+                let _ = write!(csv, ",{c}");
+            }
+            for c in cluster.min.iter().chain(cluster.max.iter()) {
+                let _ = write!(csv, ",{c}");
+            }
+            csv.push('\n');
+        }
+        csv
     }
 }
 
@@ -109,6 +671,8 @@ impl Connections {
 struct Circuits {
     parent: Vec<usize>,
     size: Vec<usize>,
+    count: usize,
+    largest: usize,
 }
 
 impl From<usize> for Circuits {
@@ -120,6 +684,8 @@ impl From<usize> for Circuits {
             // Memory optimization: preallocate parent and size vectors
             parent: (0..n).collect(),
             size: vec![1; n],
+            count: n,
+            largest: n.min(1),
         }
     }
 }
@@ -138,15 +704,19 @@ impl Circuits {
     /**
      * `Union`
      */
-    fn connect(&mut self, x: usize, y: usize) {
+    fn connect(&mut self, x: usize, y: usize) -> bool {
         let (mut x, mut y) = (self.find(x), self.find(y));
-        if x != y {
-            if self.size[x] < self.size[y] {
-                swap(&mut x, &mut y);
-            }
-            self.parent[y] = x;
-            self.size[x] += self.size[y];
+        if x == y {
+            return false;
+        }
+        if self.size[x] < self.size[y] {
+            swap(&mut x, &mut y);
         }
+        self.parent[y] = x;
+        self.size[x] += self.size[y];
+        self.count -= 1;
+        self.largest = self.largest.max(self.size[x]);
+        true
     }
 
     fn sizes(&mut self) -> Vec<usize> {
@@ -164,10 +734,6 @@ impl Circuits {
         circuits.sort_unstable();
         circuits.into_iter()
     }
-
-    fn is_all_connected(&mut self) -> bool {
-        self.sizes().len() == 1
-    }
 }
 
 #[cfg(test)]
@@ -184,9 +750,9 @@ mod test {
             "0,5,6".to_string(),
         ];
 
-        let result = p1(&input, 2);
+        let result = p1(&input, 2, Metric::Euclidean);
 
-        assert_eq!(result, 3); // = 3 * 1 * 1
+        assert_eq!(result, Ok(3)); // = 3 * 1 * 1
         // Explanation : <(0,0,0>,(1,0,0),(0,2,0)> * <(0,0,9)> * <(0,5,6)>
     }
 
@@ -215,9 +781,9 @@ mod test {
             "425,690,689".to_string(),
         ];
 
-        let result = p1(&input, 10);
+        let result = p1(&input, 10, Metric::Euclidean);
 
-        assert_eq!(result, 40);
+        assert_eq!(result, Ok(40));
     }
 
     #[test]
@@ -230,9 +796,9 @@ mod test {
             "13,10,10".to_string(), // d
         ];
 
-        let result = p2(&input);
+        let result = p2(&input, Metric::Euclidean);
 
-        assert_eq!(result, 130); // Last connection <(10,10,10),(13,10,10)> => x1 * x2 => 130
+        assert_eq!(result, Ok(130)); // Last connection <(10,10,10),(13,10,10)> => x1 * x2 => 130
     }
 
     #[test]
@@ -245,9 +811,9 @@ mod test {
             "30,10,10".to_string(), // d
         ];
 
-        let result = p2(&input);
+        let result = p2(&input, Metric::Euclidean);
 
-        assert_eq!(result, 300); // Last connection <(10,10,10),(30,10,10)> => x1 * x2 => 300
+        assert_eq!(result, Ok(300)); // Last connection <(10,10,10),(30,10,10)> => x1 * x2 => 300
     }
 
     #[test]
@@ -275,8 +841,173 @@ mod test {
             "425,690,689".to_string(),
         ];
 
-        let result = p2(&input);
+        let result = p2(&input, Metric::Euclidean);
+
+        assert_eq!(result, Ok(25272)); // Last connection <(216,146,977),(117,168,530)> => x1 * x2 => 25272
+    }
+
+    /**
+     * Pseudo-random cloud with many equal distances
+     */
+    fn point_cloud() -> Vec<JunctionBox> {
+        (0..300u64)
+            .map(|i| JunctionBox {
+                coordinates: vec![(i * 7919) % 31, (i * 104_729) % 17, (i * 1_299_709) % 23],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn connections_match_sorted_pairs() {
+        let junction_boxes = point_cloud();
+
+        let mut expected = vec![];
+        for (i, a) in junction_boxes.iter().enumerate() {
+            for (j, b) in junction_boxes.iter().enumerate().skip(i + 1) {
+                expected.push((i, j, a.distance(b, Metric::Euclidean)));
+            }
+        }
+        expected.sort_by_key(|&(_, _, distance)| distance);
+
+        let connections: Vec<_> = Connections::from(&junction_boxes, Metric::Euclidean).collect();
+
+        assert_eq!(connections, expected);
+    }
+
+    #[test]
+    fn spanning_tree_matches_kruskal() {
+        let junction_boxes = point_cloud();
+
+        for metric in [Metric::Euclidean, Metric::Manhattan, Metric::Chebyshev] {
+            let mut circuits = Circuits::from(junction_boxes.len());
+            let expected: Vec<(usize, usize)> = Connections::from(&junction_boxes, metric)
+                .filter(|&(a, b, _)| circuits.connect(a, b))
+                .map(|(a, b, _)| (a, b))
+                .collect();
+
+            let spanning_tree = SpanningTree::from(&junction_boxes, metric);
+            let edges: Vec<(usize, usize)> = spanning_tree
+                .edges
+                .iter()
+                .map(|edge| (edge.a, edge.b))
+                .collect();
+
+            assert_eq!(edges, expected);
+        }
+    }
+
+    #[test]
+    fn p2_scales_with_an_outlier() {
+        // Uniform cloud, then one junction box far away connected last
+        let mut input: Vec<String> = (0..5_000u64)
+            .map(|i| {
+                format!(
+                    "{},{},{}",
+                    (i * 7919) % 10_007,
+                    (i * 104_729) % 10_009,
+                    (i * 1_299_709) % 10_037
+                )
+            })
+            .collect();
+        input.push("1000000,1000000,1000000".to_string());
+        let junction_boxes = parse_junction_boxes(&input).unwrap();
+        let outlier = &junction_boxes[5_000];
+        let nearest = junction_boxes[..5_000]
+            .iter()
+            .min_by_key(|b| b.distance(outlier, Metric::Euclidean))
+            .unwrap();
+
+        let result = p2(&input, Metric::Euclidean);
+
+        assert_eq!(result, Ok(1_000_000 * nearest.coordinates[0]));
+    }
+
+    #[test]
+    fn spanning_tree_csv() {
+        let input = vec![
+            "10,10,10".to_string(),
+            "10,10,11".to_string(),
+            "10,12,10".to_string(),
+            "13,10,10".to_string(),
+        ];
+        let junction_boxes = parse_junction_boxes(&input).unwrap();
+
+        let spanning_tree = SpanningTree::from(&junction_boxes, Metric::Euclidean);
+
+        assert!((spanning_tree.total_weight() - 6.0).abs() < f64::EPSILON);
+        assert_eq!(
+            spanning_tree.to_csv(),
+            "a,b,squared_distance,distance,circuits,largest_circuit\n\
+             0,1,1,1,3,2\n\
+             0,2,4,2,2,3\n\
+             0,3,9,3,1,4\n"
+        );
+    }
+
+    #[test]
+    fn p2_other_metrics_and_dimensions() {
+        let input = vec![
+            "0,0".to_string(),  // a
+            "4,0".to_string(),  // b
+            "7,3".to_string(),  // c
+            "10,0".to_string(), // d
+        ];
+
+        // Connection order : (a,b), (b,c), (c,d)
+        assert_eq!(p2(&input, Metric::Euclidean), Ok(70));
+        // Connection order : (a,b), (b,c), (b,d)
+        assert_eq!(p2(&input, Metric::Manhattan), Ok(40));
+        // Connection order : (b,c), (c,d), (a,b)
+        assert_eq!(p2(&input, Metric::Chebyshev), Ok(0));
+    }
+
+    #[test]
+    fn invalid_junction_boxes() {
+        let input = vec!["1,2,3".to_string(), "4,5".to_string()];
+        assert_eq!(
+            p2(&input, Metric::Euclidean),
+            Err(JunctionBoxError::DimensionMismatch {
+                line: 2,
+                expected: 3,
+                found: 2
+            })
+        );
+
+        let input = vec!["1,2,3".to_string(), "4,x,6".to_string()];
+        assert_eq!(
+            p1(&input, 1, Metric::Euclidean),
+            Err(JunctionBoxError::InvalidCoordinate {
+                line: 2,
+                value: "x".to_string()
+            })
+        );
+    }
 
-        assert_eq!(result, 25272); // Last connection <(216,146,977),(117,168,530)> => x1 * x2 => 25272
+    #[test]
+    fn clustering_with_threshold() {
+        let input = vec![
+            "0,0".to_string(),
+            "20,20".to_string(),
+            "1,0".to_string(),
+            "21,22".to_string(),
+            "2,1".to_string(),
+            "50,50".to_string(),
+        ];
+        let junction_boxes = parse_junction_boxes(&input).unwrap();
+
+        let clustering = Clustering::from(&junction_boxes, Metric::Euclidean, 2.5);
+
+        assert_eq!(clustering.labels, vec![0, 1, 0, 1, 0, 2]);
+        assert_eq!(
+            clustering.clusters_csv(),
+            "label,size,centroid_0,centroid_1,min_0,min_1,max_0,max_1\n\
+             0,3,1,0.3333333333333333,0,0,2,1\n\
+             1,2,20.5,21,20,20,21,22\n\
+             2,1,50,50,50,50,50,50\n"
+        );
+
+        // Chebyshev distance between (20,20) and (21,22) is exactly 2
+        let clustering = Clustering::from(&junction_boxes, Metric::Chebyshev, 2.0);
+        assert_eq!(clustering.labels, vec![0, 1, 0, 2, 0, 3]);
     }
 }
@@ -1,20 +1,57 @@
 use std::{
-    collections::HashMap,
-    fs::File,
+    collections::{HashMap, VecDeque},
+    env,
+    error::Error,
+    fmt::{self, Write},
+    fs::{self, File},
     io::{self, BufRead, BufReader},
 };
 
 fn main() -> io::Result<()> {
     let input = read_input()?;
 
-    let p1_result = p1(&input);
+    let args: Vec<String> = env::args().collect();
+    if args.iter().any(|arg| arg == "--validate") {
+        let floor = Floor::try_from(input.as_slice()).map_err(invalid_data)?;
+        print!("{}", floor.validate());
+        return Ok(());
+    }
+
+    let p1_result = p1(&input).map_err(invalid_data)?;
 
     println!("Part 1 result: {p1_result}");
 
-    let p2_result = p2(&input);
+    let p2_result = p2(&input).map_err(invalid_data)?;
 
     println!("Part 2 result: {p2_result}");
 
+    if args.iter().any(|arg| arg == "--any-corners") {
+        let floor = Floor::try_from(input.as_slice()).map_err(invalid_data)?;
+        let report = floor.validate();
+        if report.has_errors() {
+            return Err(invalid_data(FloorError::InvalidPolygon(report.issues)));
+        }
+        for (name, rectangle) in [
+            ("Free corners", floor.find_maximum_free_rectangle_inside()),
+            (
+                "One red corner",
+                floor.find_maximum_anchored_rectangle_inside(),
+            ),
+        ] {
+            match rectangle {
+                Some(rectangle) => println!("{name} result: {} ({rectangle})", rectangle.area()),
+                None => println!("{name} result: 0"),
+            }
+        }
+    }
+
+    if let Some(path) = option_value(&args, "--svg") {
+        let floor = Floor::try_from(input.as_slice()).map_err(invalid_data)?;
+        let largest = floor.find_maximum_rectangle();
+        let largest_inside = floor.find_maximum_rectangle_inside();
+        fs::write(path, floor.to_svg(&largest, largest_inside.as_ref()))?;
+    }
+
     Ok(())
 }
 
@@ -23,15 +60,70 @@ fn read_input() -> io::Result<Vec<String>> {
     BufReader::new(file).lines().collect::<Result<_, _>>()
 }
 
-fn p1(input: &[String]) -> u64 {
-    Floor::from(input).find_maximum_rectangle()
+fn invalid_data(error: FloorError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn option_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+fn p1(input: &[String]) -> Result<u64, FloorError> {
+    Ok(Floor::try_from(input)?.find_maximum_rectangle().area())
+}
+
+fn p2(input: &[String]) -> Result<u64, FloorError> {
+    let floor = Floor::try_from(input)?;
+    let report = floor.validate();
+    if report.has_errors() {
+        return Err(FloorError::InvalidPolygon(report.issues));
+    }
+
+    Ok(floor
+        .find_maximum_rectangle_inside()
+        .as_ref()
+        .map_or(0, Rectangle::area))
 }
 
-fn p2(input: &[String]) -> u64 {
-    Floor::from(input).find_maximum_rectangle_inside()
+#[derive(Debug, PartialEq, Eq)]
+enum FloorError {
+    InvalidTile { line: usize, value: String },
+    TooFewTiles(usize),
+    InvalidPolygon(Vec<PolygonIssue>),
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+impl fmt::Display for FloorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FloorError::InvalidTile { line, value } => {
+                write!(f, "invalid tile {value:?} at line {line}")
+            }
+            FloorError::TooFewTiles(count) => {
+                write!(f, "{count} red tiles, at least 2 expected")
+            }
+            FloorError::InvalidPolygon(issues) => {
+                write!(f, "invalid polygon")?;
+                for issue in issues.iter().filter(|issue| issue.is_error()) {
+                    write!(f, ", {issue}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for FloorError {}
+
+const SVG_SIZE: u32 = 800;
+const RED: &str = "rgb(220,40,40)";
+const GREEN: &str = "rgb(120,200,120)";
+const BLUE: &str = "rgb(40,80,220)";
+const ORANGE: &str = "rgb(240,140,0)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct Tile {
     x: u32,
     y: u32,
@@ -41,24 +133,9 @@ impl Tile {
     fn from(x: u32, y: u32) -> Self {
         Tile { x, y }
     }
-
-    fn is_strictly_inside(self, rectangle: &Rectangle) -> bool {
-        self.x > rectangle.top_left.x
-            && self.x < rectangle.bottom_right.x
-            && self.y > rectangle.top_left.y
-            && self.y < rectangle.bottom_right.y
-    }
-
-    fn diagonal_neighbors(self) -> [Tile; 4] {
-        [
-            Self::from(self.x - 1, self.y - 1),
-            Self::from(self.x + 1, self.y - 1),
-            Self::from(self.x - 1, self.y + 1),
-            Self::from(self.x + 1, self.y + 1),
-        ]
-    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct Rectangle {
     top_left: Tile,
     bottom_right: Tile,
@@ -84,165 +161,599 @@ impl Rectangle {
     }
 }
 
+impl fmt::Display for Rectangle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{} to {},{}",
+            self.top_left.x, self.top_left.y, self.bottom_right.x, self.bottom_right.y
+        )
+    }
+}
+
 struct Floor {
     red_tiles: Vec<Tile>,
 }
 
-impl From<&[String]> for Floor {
-    fn from(input: &[String]) -> Self {
+impl TryFrom<&[String]> for Floor {
+    type Error = FloorError;
+
+    fn try_from(input: &[String]) -> Result<Self, Self::Error> {
         let red_tiles: Vec<Tile> = input
             .iter()
-            .map(|line| {
-                let (x, y) = line.split_once(',').unwrap();
-                Tile::from(x.parse().unwrap(), y.parse().unwrap())
+            .enumerate()
+            .map(|(i, line)| {
+                let invalid_tile = || FloorError::InvalidTile {
+                    line: i + 1,
+                    value: line.clone(),
+                };
+                let (x, y) = line.split_once(',').ok_or_else(invalid_tile)?;
+                match (x.trim().parse(), y.trim().parse()) {
+                    (Ok(x), Ok(y)) => Ok(Tile::from(x, y)),
+                    _ => Err(invalid_tile()),
+                }
             })
-            .collect();
+            .collect::<Result<_, _>>()?;
 
-        Floor { red_tiles }
+        if red_tiles.len() < 2 {
+            return Err(FloorError::TooFewTiles(red_tiles.len()));
+        }
+
+        Ok(Floor { red_tiles })
     }
 }
 
 impl Floor {
-    fn find_maximum_rectangle(&self) -> u64 {
+    fn find_maximum_rectangle(&self) -> Rectangle {
         self.rectangle_candidates()
-            .iter()
-            .map(|(_, rectangle)| rectangle.area())
-            .max()
+            .into_iter()
+            .max_by_key(Rectangle::area)
             .unwrap()
     }
 
-    fn find_maximum_rectangle_inside(&self) -> u64 {
-        let mut candidates: Vec<(u64, Rectangle)> = self.rectangle_candidates();
+    fn find_maximum_rectangle_inside(&self) -> Option<Rectangle> {
+        let compressed_floor = CompressedFloor::from(self);
 
-        candidates.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+        self.rectangle_candidates()
+            .into_iter()
+            .filter(|rect| compressed_floor.is_inside(rect))
+            .max_by_key(Rectangle::area)
+    }
 
-        let visited = &mut HashMap::new();
+    /**
+     * Any inside rectangle can grow to the boundaries of the compressed cells it touches,
+     * so the largest one is a largest rectangle in the histogram of inside cells above each row
+     */
+    fn find_maximum_free_rectangle_inside(&self) -> Option<Rectangle> {
+        let compressed_floor = CompressedFloor::from(self);
+        let (xs, ys) = (compressed_floor.x_bounds(), compressed_floor.y_bounds());
+        let width = xs.len() - 1;
+        let mut heights = vec![0; width];
+        let mut best: Option<Rectangle> = None;
+
+        for y in 0..ys.len() - 1 {
+            for (x, height) in heights.iter_mut().enumerate() {
+                *height = if compressed_floor.is_inside_cell(x, y) {
+                    *height + ys[y + 1] - ys[y]
+                } else {
+                    0
+                };
+            }
 
-        candidates
-            .iter()
-            .find(|(_, rect)| self.is_rectangle_inside_polygon(rect, visited))
-            .map_or(0, |(area, _)| *area)
+            let mut stack: Vec<usize> = Vec::new();
+            for x in 0..=width {
+                let height = heights.get(x).copied().unwrap_or(0);
+                while let Some(&top) = stack.last()
+                    && heights[top] >= height
+                {
+                    stack.pop();
+                    let left = stack.last().map_or(0, |&left| left + 1);
+                    if heights[top] == 0 || xs[x] == xs[left] {
+                        continue;
+                    }
+                    let rectangle = Rectangle::from(
+                        Tile::from(xs[left], ys[y + 1] - heights[top]),
+                        Tile::from(xs[x] - 1, ys[y + 1] - 1),
+                    );
+                    if best.is_none_or(|best| rectangle.area() > best.area()) {
+                        best = Some(rectangle);
+                    }
+                }
+                stack.push(x);
+            }
+        }
+
+        best
     }
 
-    fn rectangle_candidates(&self) -> Vec<(u64, Rectangle)> {
+    /**
+     * Grows a rectangle from every red tile in each of the four directions,
+     * column by column, as high as every column crossed so far allows
+     */
+    fn find_maximum_anchored_rectangle_inside(&self) -> Option<Rectangle> {
+        let compressed_floor = CompressedFloor::from(self);
+        let (xs, ys) = (compressed_floor.x_bounds(), compressed_floor.y_bounds());
+        let (width, height) = (xs.len() - 1, ys.len() - 1);
+
+        // Inside length from the start of a cell downwards, and from its end upwards
+        let mut down = vec![vec![0; width]; height + 1];
+        let mut up = vec![vec![0; width]; height + 1];
+        for y in (0..height).rev() {
+            let (row, below) = down.split_at_mut(y + 1);
+            for (x, run) in row[y].iter_mut().enumerate() {
+                if compressed_floor.is_inside_cell(x, y) {
+                    *run = below[0][x] + ys[y + 1] - ys[y];
+                }
+            }
+        }
+        for y in 0..height {
+            let (above, row) = up.split_at_mut(y + 1);
+            for (x, run) in row[0].iter_mut().enumerate() {
+                if compressed_floor.is_inside_cell(x, y) {
+                    *run = above[y][x] + ys[y + 1] - ys[y];
+                }
+            }
+        }
+
+        let mut best: Option<Rectangle> = None;
+        for tile in &self.red_tiles {
+            let (cx, cy) = compressed_floor.cell(*tile);
+            let rightwards = || (cx..width).map(|x| (x, xs[x + 1] - 1));
+            let leftwards = || (0..=cx).rev().map(|x| (x, xs[x]));
+            let quadrants: [(Box<dyn Iterator<Item = _>>, _, _); 4] = [
+                (Box::new(rightwards()), &down[cy], true),
+                (Box::new(rightwards()), &up[cy + 1], false),
+                (Box::new(leftwards()), &down[cy], true),
+                (Box::new(leftwards()), &up[cy + 1], false),
+            ];
+
+            for (columns, runs, downwards) in quadrants {
+                let mut run = u32::MAX;
+                for (x, far_x) in columns {
+                    run = run.min(runs[x]);
+                    if run == 0 {
+                        break;
+                    }
+                    let far_y = if downwards {
+                        tile.y + run - 1
+                    } else {
+                        tile.y + 1 - run
+                    };
+                    let rectangle = Rectangle::from(*tile, Tile::from(far_x, far_y));
+                    if best.is_none_or(|best| rectangle.area() > best.area()) {
+                        best = Some(rectangle);
+                    }
+                }
+            }
+        }
+
+        best
+    }
+
+    fn rectangle_candidates(&self) -> Vec<Rectangle> {
         let n = self.red_tiles.len();
         let mut candidates = Vec::with_capacity((n * (n - 1)) / 2);
 
         for (i, a) in self.red_tiles.iter().enumerate() {
             for b in self.red_tiles.iter().skip(i + 1) {
-                let rect = Rectangle::from(*a, *b);
-                let area = rect.area();
-                candidates.push((area, rect));
+                candidates.push(Rectangle::from(*a, *b));
             }
         }
 
         candidates
     }
 
-    fn is_rectangle_inside_polygon(
-        &self,
-        rectangle: &Rectangle,
-        visited: &mut HashMap<Tile, bool>,
-    ) -> bool {
-        self.iter_interior_red_tiles(rectangle)
-            .chain(self.iter_horizontal_projections(rectangle))
-            .chain(self.iter_vertical_projections(rectangle))
-            .all(|tile| self.is_green_tile(tile, visited))
-    }
+    /**
+     * Red tiles are expected to form a closed rectilinear polygon, in order
+     * Tile `i` comes from line `i + 1`
+     */
+    fn validate(&self) -> PolygonReport {
+        let mut issues = Vec::new();
+        let n = self.red_tiles.len();
+        let line = |i: usize| i % n + 1;
 
-    fn iter_interior_red_tiles<'a>(
-        &'a self,
-        rectangle: &'a Rectangle,
-    ) -> impl Iterator<Item = Tile> + 'a {
-        self.red_tiles
-            .iter()
-            .filter_map(move |red_tile| {
-                if red_tile.is_strictly_inside(rectangle) {
-                    Some(red_tile.diagonal_neighbors())
+        if n < 4 {
+            issues.push(PolygonIssue::TooFewVertices(n));
+        }
+
+        let mut first_lines = HashMap::new();
+        for (i, tile) in self.red_tiles.iter().enumerate() {
+            if let Some(first_line) = first_lines.insert(*tile, line(i)) {
+                issues.push(PolygonIssue::DuplicateVertex {
+                    first_line,
+                    line: line(i),
+                });
+            }
+            if tile.x == u32::MAX || tile.y == u32::MAX {
+                issues.push(PolygonIssue::CoordinateMax { line: line(i) });
+            } else if tile.x == 0 || tile.y == 0 {
+                issues.push(PolygonIssue::CoordinateZero { line: line(i) });
+            }
+        }
+
+        let edges: Vec<(Tile, Tile)> = self.edges().collect();
+        for (i, (a, b)) in edges.iter().enumerate() {
+            if a.x != b.x && a.y != b.y {
+                issues.push(PolygonIssue::DiagonalEdge {
+                    from_line: line(i),
+                    to_line: line(i + 1),
+                });
+            }
+        }
+
+        // Diagonal edges are already reported, intersections are only checked between aligned ones
+        let aligned = |(a, b): (Tile, Tile)| a.x == b.x || a.y == b.y;
+        for i in (0..n).filter(|&i| aligned(edges[i])) {
+            for j in (i + 1..n).filter(|&j| aligned(edges[j])) {
+                let adjacent = j == i + 1 || (i == 0 && j == n - 1);
+                let (a, b) = (edges[i], edges[j]);
+                let crossing = if adjacent {
+                    Self::doubles_back(a, b) || Self::doubles_back(b, a)
                 } else {
-                    None
+                    Self::touches(a, b)
+                };
+                if crossing {
+                    issues.push(PolygonIssue::SelfIntersection {
+                        first_edge: (line(i), line(i + 1)),
+                        second_edge: (line(j), line(j + 1)),
+                    });
                 }
-            })
-            .flatten()
+            }
+        }
+
+        PolygonReport {
+            orientation: self.orientation(),
+            issues,
+        }
+    }
+
+    /**
+     * Sign of the shoelace sum, with y growing downwards as on the puzzle's floor
+     */
+    fn orientation(&self) -> Orientation {
+        let twice_area: i128 = self
+            .edges()
+            .map(|(a, b)| i128::from(a.x) * i128::from(b.y) - i128::from(b.x) * i128::from(a.y))
+            .sum();
+
+        match twice_area.signum() {
+            1 => Orientation::Clockwise,
+            -1 => Orientation::CounterClockwise,
+            _ => Orientation::Degenerate,
+        }
     }
 
-    fn iter_horizontal_projections<'a>(
-        &'a self,
-        rectangle: &'a Rectangle,
-    ) -> impl Iterator<Item = Tile> + 'a {
+    /**
+     * Edges sharing a vertex only overlap when the second one goes back along the first
+     */
+    fn doubles_back((a, b): (Tile, Tile), (c, d): (Tile, Tile)) -> bool {
+        if b != c {
+            return false;
+        }
+        let same_line = (a.x == b.x && b.x == d.x) || (a.y == b.y && b.y == d.y);
+        let backwards = (a.x.cmp(&b.x) == d.x.cmp(&b.x)) && (a.y.cmp(&b.y) == d.y.cmp(&b.y));
+        same_line && backwards && d != b
+    }
+
+    fn touches(a: (Tile, Tile), b: (Tile, Tile)) -> bool {
+        let (a, b) = (Rectangle::from(a.0, a.1), Rectangle::from(b.0, b.1));
+        a.top_left.x <= b.bottom_right.x
+            && b.top_left.x <= a.bottom_right.x
+            && a.top_left.y <= b.bottom_right.y
+            && b.top_left.y <= a.bottom_right.y
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (Tile, Tile)> + '_ {
         self.red_tiles
             .iter()
-            .filter_map(move |red_tile| {
-                if red_tile.y > rectangle.top_left.y && red_tile.y < rectangle.bottom_right.y {
-                    if red_tile.x <= rectangle.top_left.x {
-                        return Some([
-                            Tile::from(rectangle.top_left.x, red_tile.y + 1),
-                            Tile::from(rectangle.top_left.x, red_tile.y - 1),
-                        ]);
-                    } else if red_tile.x >= rectangle.bottom_right.x {
-                        return Some([
-                            Tile::from(rectangle.bottom_right.x, red_tile.y + 1),
-                            Tile::from(rectangle.bottom_right.x, red_tile.y - 1),
-                        ]);
-                    }
-                }
-                None
-            })
-            .flatten()
+            .zip(self.red_tiles.iter().cycle().skip(1))
+            .map(|(a, b)| (*a, *b))
     }
+}
 
-    fn iter_vertical_projections<'a>(
-        &'a self,
-        rectangle: &'a Rectangle,
-    ) -> impl Iterator<Item = Tile> + 'a {
-        self.red_tiles
+/**
+ * Tile `(x, y)` is the unit square from `(x, y)` to `(x + 1, y + 1)`
+ */
+impl Floor {
+    fn to_svg(&self, largest: &Rectangle, largest_inside: Option<&Rectangle>) -> String {
+        let x_min = self.red_tiles.iter().map(|tile| tile.x).min().unwrap();
+        let x_max = self.red_tiles.iter().map(|tile| tile.x).max().unwrap();
+        let y_min = self.red_tiles.iter().map(|tile| tile.y).min().unwrap();
+        let y_max = self.red_tiles.iter().map(|tile| tile.y).max().unwrap();
+        let span = (x_max - x_min).max(y_max - y_min) + 1;
+        let margin = span / 20 + 1;
+        let font_size = span / 30 + 1;
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{SVG_SIZE}\" height=\"{SVG_SIZE}\" viewBox=\"{} {} {} {}\">\n\
+             <rect x=\"{}\" y=\"{}\" width=\"100%\" height=\"100%\" fill=\"white\"/>\n",
+            i64::from(x_min) - i64::from(margin),
+            i64::from(y_min) - i64::from(margin),
+            span + 2 * margin,
+            span + 2 * margin,
+            i64::from(x_min) - i64::from(margin),
+            i64::from(y_min) - i64::from(margin),
+        );
+
+        // A polygon through tile centres, stroked one tile wide, covers exactly the green tiles
+        let points: Vec<String> = self
+            .red_tiles
             .iter()
-            .filter_map(move |red_tile| {
-                if red_tile.x > rectangle.top_left.x && red_tile.x < rectangle.bottom_right.x {
-                    if red_tile.y <= rectangle.top_left.y {
-                        return Some([
-                            Tile::from(red_tile.x + 1, rectangle.top_left.y),
-                            Tile::from(red_tile.x - 1, rectangle.top_left.y),
-                        ]);
-                    } else if red_tile.y >= rectangle.bottom_right.y {
-                        return Some([
-                            Tile::from(red_tile.x + 1, rectangle.bottom_right.y),
-                            Tile::from(red_tile.x - 1, rectangle.bottom_right.y),
-                        ]);
-                    }
+            .map(|tile| format!("{}.5,{}.5", tile.x, tile.y))
+            .collect();
+        let _ = writeln!(
+            svg,
+            "<polygon points=\"{}\" fill=\"{GREEN}\" stroke=\"{GREEN}\" stroke-width=\"1\" stroke-linejoin=\"miter\"/>",
+            points.join(" ")
+        );
+        for tile in &self.red_tiles {
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{}\" y=\"{}\" width=\"1\" height=\"1\" fill=\"{RED}\"><title>{},{}</title></rect>",
+                tile.x, tile.y, tile.x, tile.y
+            );
+        }
+
+        // The largest rectangle is labelled above its top edge, the inside one along its bottom edge
+        let labelled = [
+            (Some(largest), BLUE, "largest", largest.top_left.y),
+            (
+                largest_inside,
+                ORANGE,
+                "inside",
+                largest_inside.map_or(0, |rectangle| rectangle.bottom_right.y + 1),
+            ),
+        ];
+        for (rectangle, color, label, label_y) in labelled {
+            let Some(rectangle) = rectangle else {
+                continue;
+            };
+            let (top_left, bottom_right) = (rectangle.top_left, rectangle.bottom_right);
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"{color}\" stroke-width=\"2\" vector-effect=\"non-scaling-stroke\"/>\n\
+                 <text x=\"{}\" y=\"{label_y}\" font-size=\"{font_size}\" fill=\"{color}\">{label} {}</text>",
+                top_left.x,
+                top_left.y,
+                bottom_right.x - top_left.x + 1,
+                bottom_right.y - top_left.y + 1,
+                top_left.x,
+                rectangle.area()
+            );
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+/**
+ * Coordinates `c` and `c + 1` of red tiles split the floor into cells of uniform colour,
+ * cell `i` spanning `xs[i]..xs[i + 1]`
+ * The grid is padded with one outside cell on each side
+ */
+struct CompressedFloor {
+    xs: Vec<u32>,
+    ys: Vec<u32>,
+    outside_prefix_sums: Vec<Vec<u32>>,
+}
+
+impl From<&Floor> for CompressedFloor {
+    fn from(floor: &Floor) -> Self {
+        let compress = |coordinate: fn(&Tile) -> u32| {
+            let mut values: Vec<u32> = floor
+                .red_tiles
+                .iter()
+                .flat_map(|tile| [coordinate(tile), coordinate(tile) + 1])
+                .collect();
+            values.sort_unstable();
+            values.dedup();
+            values
+        };
+        let xs = compress(|tile| tile.x);
+        let ys = compress(|tile| tile.y);
+
+        let (width, height) = (xs.len() + 2, ys.len() + 2);
+        let mut boundary = vec![vec![false; width]; height];
+        let mut compressed_floor = CompressedFloor {
+            xs,
+            ys,
+            outside_prefix_sums: vec![vec![0; width + 1]; height + 1],
+        };
+
+        for (a, b) in floor.edges() {
+            let rect = Rectangle::from(a, b);
+            let (x_min, y_min) = compressed_floor.cell(rect.top_left);
+            let (x_max, y_max) = compressed_floor.cell(rect.bottom_right);
+            for row in &mut boundary[y_min..=y_max] {
+                row[x_min..=x_max].fill(true);
+            }
+        }
+
+        // Flood fill from the padding, the boundary is a closed loop of tiles
+        let mut outside = vec![vec![false; width]; height];
+        let mut queue = VecDeque::from([(0_usize, 0_usize)]);
+        outside[0][0] = true;
+        while let Some((x, y)) = queue.pop_front() {
+            for (nx, ny) in [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ] {
+                if nx < width && ny < height && !boundary[ny][nx] && !outside[ny][nx] {
+                    outside[ny][nx] = true;
+                    queue.push_back((nx, ny));
                 }
-                None
-            })
-            .flatten()
+            }
+        }
+
+        let sums = &mut compressed_floor.outside_prefix_sums;
+        for y in 0..height {
+            for x in 0..width {
+                sums[y + 1][x + 1] =
+                    sums[y][x + 1] + sums[y + 1][x] - sums[y][x] + u32::from(outside[y][x]);
+            }
+        }
+
+        compressed_floor
     }
+}
 
-    fn is_green_tile(&self, tile: Tile, visited: &mut HashMap<Tile, bool>) -> bool {
-        visited.get(&tile).copied().unwrap_or_else(|| {
-            let inside = self.is_inside_polygon(tile);
-            visited.insert(tile, inside);
-            inside
-        })
+impl CompressedFloor {
+    /**
+     * Padded cell holding a red tile coordinate
+     */
+    fn cell(&self, tile: Tile) -> (usize, usize) {
+        let x = self.xs.binary_search(&tile.x).unwrap();
+        let y = self.ys.binary_search(&tile.y).unwrap();
+        (x + 1, y + 1)
     }
 
-    fn is_inside_polygon(&self, tile: Tile) -> bool {
-        let mut inside = false;
-        let n = self.red_tiles.len();
+    /**
+     * Real coordinate where each padded cell starts, padding and the trailing cell past
+     * the last red tile being empty
+     */
+    fn x_bounds(&self) -> Vec<u32> {
+        Self::bounds(&self.xs)
+    }
 
-        for i in 0..n {
-            let Tile { x: x1, y: y1 } = self.red_tiles[i];
-            let Tile { x: x2, y: y2 } = self.red_tiles[(i + 1) % n];
-
-            if (y1 > tile.y) != (y2 > tile.y) {
-                let x_intersect = (i64::from(x2) - i64::from(x1))
-                    * (i64::from(tile.y) - i64::from(y1))
-                    / (i64::from(y2) - i64::from(y1))
-                    + i64::from(x1);
-                if i64::from(tile.x) < x_intersect {
-                    inside = !inside;
-                }
+    fn y_bounds(&self) -> Vec<u32> {
+        Self::bounds(&self.ys)
+    }
+
+    fn bounds(values: &[u32]) -> Vec<u32> {
+        let (first, last) = (values[0], values[values.len() - 1]);
+        [first]
+            .into_iter()
+            .chain(values.iter().copied())
+            .chain([last, last])
+            .collect()
+    }
+
+    fn is_inside_cell(&self, x: usize, y: usize) -> bool {
+        let sums = &self.outside_prefix_sums;
+        sums[y + 1][x + 1] + sums[y][x] == sums[y][x + 1] + sums[y + 1][x]
+    }
+
+    fn is_inside(&self, rectangle: &Rectangle) -> bool {
+        let (x_min, y_min) = self.cell(rectangle.top_left);
+        let (x_max, y_max) = self.cell(rectangle.bottom_right);
+        let sums = &self.outside_prefix_sums;
+
+        sums[y_max + 1][x_max + 1] + sums[y_min][x_min]
+            == sums[y_min][x_max + 1] + sums[y_max + 1][x_min]
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Orientation {
+    Clockwise,
+    CounterClockwise,
+    Degenerate,
+}
+
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Orientation::Clockwise => write!(f, "clockwise"),
+            Orientation::CounterClockwise => write!(f, "counter-clockwise"),
+            Orientation::Degenerate => write!(f, "degenerate (zero area)"),
+        }
+    }
+}
+
+/**
+ * Line numbers of an edge are those of its two red tiles
+ */
+#[derive(Debug, PartialEq, Eq)]
+enum PolygonIssue {
+    TooFewVertices(usize),
+    DuplicateVertex {
+        first_line: usize,
+        line: usize,
+    },
+    DiagonalEdge {
+        from_line: usize,
+        to_line: usize,
+    },
+    SelfIntersection {
+        first_edge: (usize, usize),
+        second_edge: (usize, usize),
+    },
+    CoordinateZero {
+        line: usize,
+    },
+    CoordinateMax {
+        line: usize,
+    },
+}
+
+impl PolygonIssue {
+    /**
+     * Tiles at coordinate 0 are only a warning, the compressed grid pads before them with an extra cell
+     * Tiles at `u32::MAX` are errors, the compressed grid needs `c + 1` to close their cell
+     */
+    fn is_error(&self) -> bool {
+        !matches!(self, PolygonIssue::CoordinateZero { .. })
+    }
+}
+
+impl fmt::Display for PolygonIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolygonIssue::TooFewVertices(count) => {
+                write!(
+                    f,
+                    "{count} vertices, a rectilinear polygon needs at least 4"
+                )
+            }
+            PolygonIssue::DuplicateVertex { first_line, line } => {
+                write!(f, "vertex at line {line} duplicates line {first_line}")
+            }
+            PolygonIssue::DiagonalEdge { from_line, to_line } => {
+                write!(
+                    f,
+                    "edge from line {from_line} to line {to_line} is not axis-aligned"
+                )
+            }
+            PolygonIssue::SelfIntersection {
+                first_edge,
+                second_edge,
+            } => write!(
+                f,
+                "edge {}-{} intersects edge {}-{}",
+                first_edge.0, first_edge.1, second_edge.0, second_edge.1
+            ),
+            PolygonIssue::CoordinateZero { line } => {
+                write!(f, "tile at line {line} sits on coordinate 0")
+            }
+            PolygonIssue::CoordinateMax { line } => {
+                write!(f, "tile at line {line} sits on coordinate {}", u32::MAX)
             }
         }
-        inside
+    }
+}
+
+struct PolygonReport {
+    orientation: Orientation,
+    issues: Vec<PolygonIssue>,
+}
+
+impl PolygonReport {
+    fn has_errors(&self) -> bool {
+        self.issues.iter().any(PolygonIssue::is_error)
+    }
+}
+
+impl fmt::Display for PolygonReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Orientation: {}", self.orientation)?;
+        for issue in &self.issues {
+            let severity = if issue.is_error() { "error" } else { "warning" };
+            writeln!(f, "{severity}: {issue}")?;
+        }
+        Ok(())
     }
 }
 
@@ -262,7 +773,7 @@ mod test {
             "2,3".to_string(),
             "7,3".to_string(),
         ];
-        assert_eq!(p1(&input), 50); // (2,5) to (11,1) = 10 * 5
+        assert_eq!(p1(&input), Ok(50)); // (2,5) to (11,1) = 10 * 5
     }
 
     #[test]
@@ -277,12 +788,223 @@ mod test {
             "2,3".to_string(),
             "7,3".to_string(),
         ];
-        assert_eq!(p2(&input), 24); // (9,5) to (2,3) = 8 * 3
+        assert_eq!(p2(&input), Ok(24)); // (9,5) to (2,3) = 8 * 3
+    }
+
+    #[test]
+    fn p2_notched_usecase() {
+        let input = vec![
+            "0,0".to_string(),
+            "10,0".to_string(),
+            "10,10".to_string(),
+            "7,10".to_string(),
+            "7,3".to_string(),
+            "3,3".to_string(),
+            "3,10".to_string(),
+            "0,10".to_string(),
+        ];
+        assert_eq!(p2(&input), Ok(44)); // (0,0) to (3,10) = 4 * 11, the notch rules out the full square
+    }
+
+    #[test]
+    fn maximum_rectangles_corners() {
+        let input = vec![
+            "7,1".to_string(),
+            "11,1".to_string(),
+            "11,7".to_string(),
+            "9,7".to_string(),
+            "9,5".to_string(),
+            "2,5".to_string(),
+            "2,3".to_string(),
+            "7,3".to_string(),
+        ];
+        let floor = Floor::try_from(input.as_slice()).unwrap();
+        assert_eq!(
+            floor.find_maximum_rectangle(),
+            Rectangle::from(Tile::from(2, 3), Tile::from(11, 7))
+        );
+        assert_eq!(
+            floor.find_maximum_rectangle_inside(),
+            Some(Rectangle::from(Tile::from(2, 3), Tile::from(9, 5)))
+        );
+    }
+
+    #[test]
+    fn to_svg_basic_usecase() {
+        let input = vec![
+            "7,1".to_string(),
+            "11,1".to_string(),
+            "11,7".to_string(),
+            "9,7".to_string(),
+            "9,5".to_string(),
+            "2,5".to_string(),
+            "2,3".to_string(),
+            "7,3".to_string(),
+        ];
+        let floor = Floor::try_from(input.as_slice()).unwrap();
+        let largest = floor.find_maximum_rectangle();
+        let largest_inside = floor.find_maximum_rectangle_inside();
+        let svg = floor.to_svg(&largest, largest_inside.as_ref());
+        let lines: Vec<&str> = svg.lines().collect();
+
+        assert!(lines[0].starts_with("<svg ") && lines[0].ends_with("viewBox=\"1 0 12 12\">"));
+        assert_eq!(
+            lines[1],
+            "<rect x=\"1\" y=\"0\" width=\"100%\" height=\"100%\" fill=\"white\"/>"
+        );
+        assert_eq!(
+            lines[2],
+            format!(
+                "<polygon points=\"7.5,1.5 11.5,1.5 11.5,7.5 9.5,7.5 9.5,5.5 2.5,5.5 2.5,3.5 7.5,3.5\" fill=\"{GREEN}\" stroke=\"{GREEN}\" stroke-width=\"1\" stroke-linejoin=\"miter\"/>"
+            )
+        );
+        let red_tiles: Vec<&str> = lines
+            .iter()
+            .copied()
+            .filter(|line| line.contains(RED))
+            .collect();
+        assert_eq!(red_tiles.len(), 8);
+        assert_eq!(
+            red_tiles[0],
+            format!(
+                "<rect x=\"7\" y=\"1\" width=\"1\" height=\"1\" fill=\"{RED}\"><title>7,1</title></rect>"
+            )
+        );
+        assert_eq!(
+            lines[11..],
+            [
+                format!(
+                    "<rect x=\"2\" y=\"3\" width=\"10\" height=\"5\" fill=\"none\" stroke=\"{BLUE}\" stroke-width=\"2\" vector-effect=\"non-scaling-stroke\"/>"
+                ),
+                format!("<text x=\"2\" y=\"3\" font-size=\"1\" fill=\"{BLUE}\">largest 50</text>"),
+                format!(
+                    "<rect x=\"2\" y=\"3\" width=\"8\" height=\"3\" fill=\"none\" stroke=\"{ORANGE}\" stroke-width=\"2\" vector-effect=\"non-scaling-stroke\"/>"
+                ),
+                format!("<text x=\"2\" y=\"6\" font-size=\"1\" fill=\"{ORANGE}\">inside 24</text>"),
+                "</svg>".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn any_corners_basic_usecase() {
+        let input = vec![
+            "7,1".to_string(),
+            "11,1".to_string(),
+            "11,7".to_string(),
+            "9,7".to_string(),
+            "9,5".to_string(),
+            "2,5".to_string(),
+            "2,3".to_string(),
+            "7,3".to_string(),
+        ];
+        let floor = Floor::try_from(input.as_slice()).unwrap();
+        let widest_band = Rectangle::from(Tile::from(2, 3), Tile::from(11, 5));
+        assert_eq!(
+            floor.find_maximum_free_rectangle_inside(),
+            Some(widest_band)
+        );
+        assert_eq!(
+            floor.find_maximum_anchored_rectangle_inside(),
+            Some(widest_band)
+        );
+    }
+
+    #[test]
+    fn any_corners_pinwheel_usecase() {
+        // A square with an arm along each side, the largest rectangle has no red corner
+        let input = vec![
+            "2,0".to_string(),
+            "6,0".to_string(),
+            "6,2".to_string(),
+            "12,2".to_string(),
+            "12,6".to_string(),
+            "10,6".to_string(),
+            "10,12".to_string(),
+            "6,12".to_string(),
+            "6,10".to_string(),
+            "0,10".to_string(),
+            "0,6".to_string(),
+            "2,6".to_string(),
+        ];
+        let floor = Floor::try_from(input.as_slice()).unwrap();
+        assert_eq!(
+            floor.find_maximum_free_rectangle_inside(),
+            Some(Rectangle::from(Tile::from(2, 2), Tile::from(10, 10)))
+        );
+        assert_eq!(
+            floor
+                .find_maximum_anchored_rectangle_inside()
+                .map(|rectangle| rectangle.area()),
+            Some(55)
+        );
+        assert_eq!(p2(&input), Ok(55));
+    }
+
+    #[test]
+    fn validate_basic_usecase() {
+        let input = vec![
+            "7,1".to_string(),
+            "11,1".to_string(),
+            "11,7".to_string(),
+            "9,7".to_string(),
+            "9,5".to_string(),
+            "2,5".to_string(),
+            "2,3".to_string(),
+            "7,3".to_string(),
+        ];
+        let report = Floor::try_from(input.as_slice()).unwrap().validate();
+        assert_eq!(report.orientation, Orientation::Clockwise);
+        assert_eq!(report.issues, vec![]);
+    }
+
+    #[test]
+    fn validate_broken_polygon() {
+        let input = vec![
+            "0,0".to_string(),
+            "4,0".to_string(),
+            "4,4".to_string(),
+            "2,4".to_string(),
+            "2,0".to_string(),
+            "0,2".to_string(),
+        ];
+        let report = Floor::try_from(input.as_slice()).unwrap().validate();
+        assert_eq!(report.orientation, Orientation::Clockwise);
+        assert_eq!(
+            report.issues,
+            vec![
+                PolygonIssue::CoordinateZero { line: 1 },
+                PolygonIssue::CoordinateZero { line: 2 },
+                PolygonIssue::CoordinateZero { line: 5 },
+                PolygonIssue::CoordinateZero { line: 6 },
+                PolygonIssue::DiagonalEdge {
+                    from_line: 5,
+                    to_line: 6
+                },
+                PolygonIssue::SelfIntersection {
+                    first_edge: (1, 2),
+                    second_edge: (4, 5)
+                },
+            ]
+        );
+        assert!(matches!(p2(&input), Err(FloorError::InvalidPolygon(_))));
+    }
+
+    #[test]
+    fn p1_invalid_tile() {
+        let input = vec!["7,1".to_string(), "11;1".to_string()];
+        assert_eq!(
+            p1(&input),
+            Err(FloorError::InvalidTile {
+                line: 2,
+                value: "11;1".to_string()
+            })
+        );
     }
 
     #[test]
     fn p2_real_usecase() {
         let input = read_input().unwrap();
-        assert_eq!(p2(&input), 1_540_060_480);
+        assert_eq!(p2(&input), Ok(1_540_060_480));
     }
 }